@@ -0,0 +1,120 @@
+use bevy::prelude::*;
+use heron::prelude::*;
+
+use crate::{
+    killscreen::{GameOverEvent, KillScreen},
+    map_generator::Map,
+    minesweeper::{self, BoomEvent},
+    params::{Difficulty, OutOfBounds, Params},
+    stats::GameStats,
+    tile::{Tile, TileKind, TileState},
+};
+
+/// Thick enough that tiles don't tunnel through in a single frame
+const WALL_THICKNESS: f32 = 200.;
+
+#[derive(Debug, Component)]
+pub struct Wall;
+
+/// Half the width and height of the space inside the walls, as they were
+/// last built, which is what [`catch_escapees`] checks tiles against
+#[derive(Debug, Default)]
+pub struct ArenaBounds(pub Vec2);
+
+/// Surround the board with invisible walls so tiles can't be flung off screen
+///
+/// Runs along with `map_actions::create_map`, so the walls are sized for the
+/// chosen difficulty rather than for whatever `Params` the last game left
+pub fn build_walls(
+    difficulty: Res<Difficulty>,
+    mut bounds: ResMut<ArenaBounds>,
+    old_walls: Query<Entity, With<Wall>>,
+    mut commands: Commands,
+) {
+    for entity in old_walls.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let half = difficulty.params().arena_half_size();
+    bounds.0 = half;
+    let offset = WALL_THICKNESS / 2.;
+    // The top and bottom walls are long enough to cover the corners
+    let walls = [
+        (0., half.y + offset, half.x + WALL_THICKNESS, offset),
+        (0., -half.y - offset, half.x + WALL_THICKNESS, offset),
+        (half.x + offset, 0., offset, half.y),
+        (-half.x - offset, 0., offset, half.y),
+    ];
+    for (x, y, half_width, half_height) in walls {
+        commands
+            .spawn_bundle((Transform::from_xyz(x, y, 0.), GlobalTransform::default()))
+            .insert(RigidBody::Static)
+            .insert(CollisionShape::Cuboid {
+                half_extends: Vec3::new(half_width, half_height, WALL_THICKNESS),
+                border_radius: None,
+            })
+            .insert(Wall)
+            .insert(Name::new("Wall"));
+    }
+}
+
+/// Tiles can still make it past the walls, in which case
+/// `Params::out_of_bounds` decides what happens to them
+#[allow(clippy::too_many_arguments)]
+pub fn catch_escapees(
+    params: Res<Params>,
+    arena: Res<ArenaBounds>,
+    mut map: ResMut<Map>,
+    mut stats: ResMut<GameStats>,
+    mut tiles: Query<(Entity, &mut Tile, &mut Transform, &mut Velocity)>,
+    mut boom: EventWriter<BoomEvent>,
+    has_killscreen: Query<(), With<KillScreen>>,
+    mut game_over: EventWriter<GameOverEvent>,
+    mut commands: Commands,
+) {
+    let bounds = arena.0 + Vec2::splat(WALL_THICKNESS);
+
+    for (entity, mut tile, mut transform, mut velocity) in tiles.iter_mut() {
+        let position = transform.translation;
+        if position.x.abs() <= bounds.x && position.y.abs() <= bounds.y {
+            continue;
+        }
+
+        match params.out_of_bounds {
+            OutOfBounds::Detonate if tile.kind == TileKind::Boom => {
+                if tile.state == TileState::Exploded {
+                    continue;
+                }
+                info!("bomb {} left the arena", tile.index_in_map);
+                if has_killscreen.iter().next().is_none() {
+                    game_over.send(GameOverEvent);
+                }
+                minesweeper::detonate(
+                    &mut commands,
+                    &mut map,
+                    &mut boom,
+                    entity,
+                    &mut tile,
+                    position,
+                    0,
+                );
+            }
+            OutOfBounds::Lost => {
+                // Nobody can ask the player to reveal a tile that isn't there
+                // anymore, see `Map::is_cleared`. Tiles that were revealed
+                // earlier in the frame, before their `Velocity` went, are
+                // put back instead.
+                if tile.set_state(&mut map, TileState::Lost) {
+                    stats.lost += 1;
+                    commands.entity(entity).despawn();
+                } else {
+                    *transform = Transform::from_translation(tile.original_position);
+                }
+            }
+            OutOfBounds::Respawn | OutOfBounds::Detonate => {
+                *transform = Transform::from_translation(tile.original_position);
+                *velocity = Velocity::from(Vec2::ZERO);
+            }
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+const PARTICLES_PER_BOOM: usize = 30;
+const PARTICLE_LIFETIME: f32 = 0.6;
+const FLASH_LIFETIME: f32 = 0.25;
+const FLASH_INTENSITY: f32 = 60000.;
+const FLIP_DURATION: f32 = 0.25;
+
+pub struct ExplosionAssets {
+    pub shrapnel_mesh: Handle<Mesh>,
+    pub shrapnel_material: Handle<StandardMaterial>,
+    pub particle_mesh: Handle<Mesh>,
+    pub particle_material: Handle<StandardMaterial>,
+}
+
+impl FromWorld for ExplosionAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mut meshes = world.get_resource_mut::<Assets<Mesh>>().unwrap();
+        let shrapnel_mesh = meshes.add(Mesh::from(shape::Icosphere {
+            radius: 10.,
+            subdivisions: 1,
+        }));
+        let particle_mesh = meshes.add(Mesh::from(shape::Cube { size: 4. }));
+
+        let mut materials = world
+            .get_resource_mut::<Assets<StandardMaterial>>()
+            .unwrap();
+        let shrapnel_material = materials.add(StandardMaterial {
+            base_color: Color::rgb(0.3, 0.25, 0.2),
+            emissive: Color::rgb(0.6, 0.15, 0.),
+            ..Default::default()
+        });
+        let particle_material = materials.add(StandardMaterial {
+            base_color: Color::ORANGE,
+            emissive: Color::YELLOW,
+            unlit: true,
+            ..Default::default()
+        });
+
+        Self {
+            shrapnel_mesh,
+            shrapnel_material,
+            particle_mesh,
+            particle_material,
+        }
+    }
+}
+
+/// Despawns its entity after a while, shrinking it (or dimming it, for
+/// lights) until then
+#[derive(Component, Debug)]
+pub struct Lifetime(pub Timer);
+
+impl Lifetime {
+    pub fn from_seconds(seconds: f32) -> Self {
+        Self(Timer::from_seconds(seconds, false))
+    }
+}
+
+/// Sparks fly in a straight line, physics don't care about them
+#[derive(Component, Debug)]
+pub struct Drift(pub Vec3);
+
+/// Sparks and a flash of light, shrapnel is spawned separately by
+/// `minesweeper::go_nuclear`
+pub fn spawn_explosion(
+    commands: &mut Commands,
+    assets: &ExplosionAssets,
+    rng: &mut impl Rng,
+    source: Vec3,
+) {
+    for _ in 0..PARTICLES_PER_BOOM {
+        let angle = rng.gen_range(0. ..std::f32::consts::TAU);
+        let speed: f32 = rng.gen_range(100. ..600.);
+        let direction = Vec3::new(angle.cos(), angle.sin(), rng.gen_range(0. ..0.5));
+        commands
+            .spawn_bundle(PbrBundle {
+                mesh: assets.particle_mesh.clone(),
+                material: assets.particle_material.clone(),
+                transform: Transform::from_translation(source),
+                ..Default::default()
+            })
+            .insert(Drift(direction * speed))
+            .insert(Lifetime::from_seconds(
+                PARTICLE_LIFETIME * rng.gen_range::<f32, _>(0.5..1.),
+            ));
+    }
+
+    commands
+        .spawn_bundle(PointLightBundle {
+            transform: Transform::from_translation(source + Vec3::new(0., 0., 50.)),
+            point_light: PointLight {
+                intensity: FLASH_INTENSITY,
+                range: 400.,
+                color: Color::ORANGE,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Lifetime::from_seconds(FLASH_LIFETIME));
+}
+
+pub fn drift(time: Res<Time>, mut sparks: Query<(&Drift, &mut Transform)>) {
+    for (Drift(velocity), mut transform) in sparks.iter_mut() {
+        transform.translation += *velocity * time.delta_seconds();
+    }
+}
+
+pub fn fade_out(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut fading: Query<(
+        Entity,
+        &mut Lifetime,
+        &mut Transform,
+        Option<&mut PointLight>,
+    )>,
+) {
+    for (entity, mut lifetime, mut transform, light) in fading.iter_mut() {
+        lifetime.0.tick(time.delta());
+        if lifetime.0.finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let left = lifetime.0.percent_left();
+        match light {
+            Some(mut light) => light.intensity = FLASH_INTENSITY * left,
+            None => transform.scale = Vec3::splat(left),
+        }
+    }
+}
+
+/// A revealed tile waiting for its turn in the ripple, after which it turns
+/// over to show `material`
+#[derive(Component, Debug)]
+pub struct Revealing {
+    delay: Timer,
+    flip: Timer,
+    material: Handle<StandardMaterial>,
+    /// Empty tiles end up as a flat piece of floor
+    flatten: bool,
+}
+
+impl Revealing {
+    pub fn new(delay: f32, material: Handle<StandardMaterial>, flatten: bool) -> Self {
+        Self {
+            delay: Timer::from_seconds(delay, false),
+            flip: Timer::from_seconds(FLIP_DURATION, false),
+            material,
+            flatten,
+        }
+    }
+}
+
+pub fn flip_revealed(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut tiles: Query<(
+        Entity,
+        &mut Revealing,
+        &mut Transform,
+        &mut Handle<StandardMaterial>,
+    )>,
+) {
+    for (entity, mut revealing, mut transform, mut material) in tiles.iter_mut() {
+        if !revealing.delay.tick(time.delta()).finished() {
+            continue;
+        }
+        revealing.flip.tick(time.delta());
+        if revealing.flip.finished() {
+            transform.rotation = Quat::IDENTITY;
+            if revealing.flatten {
+                transform.scale.z = 0.1;
+            }
+            *material = revealing.material.clone();
+            commands.entity(entity).remove::<Revealing>();
+            continue;
+        }
+
+        // Edge-on halfway through, which is when the new face swaps in
+        let progress = revealing.flip.percent();
+        let angle = if progress < 0.5 {
+            progress
+        } else {
+            *material = revealing.material.clone();
+            progress - 1.
+        };
+        transform.rotation = Quat::from_rotation_x(angle * std::f32::consts::PI);
+    }
+}
@@ -0,0 +1,147 @@
+use bevy::prelude::*;
+use heron::Velocity;
+
+use crate::{
+    params::{BombTrigger, Params},
+    stats::GameStats,
+    stress::Stress,
+    tile::{Tile, TileKind},
+};
+
+#[derive(Debug, Component)]
+pub struct Hud;
+
+/// Fills up as the fastest bomb gets closer to exploding
+#[derive(Debug, Component)]
+pub struct DangerGauge;
+
+/// The clock starts ticking with the first click, which is what gets us here
+pub fn start_clock(time: Res<Time>, mut stats: ResMut<GameStats>) {
+    stats.started = Some(time.seconds_since_startup());
+}
+
+pub fn draw(asset_server: Res<AssetServer>, mut commands: Commands) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                align_self: AlignSelf::FlexStart,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(15.0),
+                    right: Val::Px(15.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: vec![TextSection {
+                    value: String::new(),
+                    style: TextStyle {
+                        font,
+                        font_size: 24.0,
+                        color: Color::WHITE,
+                    },
+                }],
+                alignment: TextAlignment {
+                    horizontal: HorizontalAlign::Right,
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        })
+        .insert(Hud);
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(130.0),
+                    right: Val::Px(15.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Px(200.0), Val::Px(12.0)),
+                ..Default::default()
+            },
+            color: Color::DARK_GRAY.into(),
+            ..Default::default()
+        })
+        .insert(Hud)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(0.0), Val::Percent(100.0)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(DangerGauge);
+        });
+}
+
+pub fn update(
+    time: Res<Time>,
+    params: Res<Params>,
+    stats: Res<GameStats>,
+    tiles: Query<(&Tile, &Velocity, &Stress)>,
+    mut hud: Query<&mut Text, With<Hud>>,
+    mut gauge: Query<(&mut Style, &mut UiColor), With<DangerGauge>>,
+) {
+    let elapsed = stats
+        .started
+        .map(|started| time.seconds_since_startup() - started)
+        .unwrap_or_default() as u64;
+    let bombs = stats.bombs_remaining(params.bomb_count);
+    let clicks = stats.clicks;
+    let bombs_in_danger = tiles
+        .iter()
+        .filter(|(tile, ..)| tile.kind == TileKind::Boom);
+    let (fastest_bomb, most_stress, danger) = bombs_in_danger.fold(
+        (0., 0., 0.),
+        |(fastest, most_stress, danger): (f32, f32, f32), (_, velocity, stress)| {
+            let speed = velocity.linear.length();
+            (
+                fastest.max(speed),
+                most_stress.max(stress.value),
+                danger.max(params.bomb_danger(speed, stress)),
+            )
+        },
+    );
+
+    if let Ok((mut style, mut color)) = gauge.get_single_mut() {
+        style.size.width = Val::Percent(100. * danger);
+        *color = Color::hsl(120. * (1. - danger), 0.8, 0.5).into();
+    }
+
+    let mut text = if let Ok(text) = hud.get_single_mut() {
+        text
+    } else {
+        return;
+    };
+    let shaking = match params.bomb_trigger {
+        BombTrigger::Velocity => {
+            let threshold = params.bomb_velocity_threshold;
+            format!("Shaking {fastest_bomb:.0} / {threshold:.0}")
+        }
+        BombTrigger::Stress(model) => {
+            let limit = model.limit;
+            format!("Stress {most_stress:.0} / {limit:.0}")
+        }
+    };
+    let lost = match stats.lost {
+        0 => String::new(),
+        n => format!("\nLost {n}"),
+    };
+    text.sections[0].value = format!(
+        "Time {}:{:02}\nBombs left {bombs}\nClicks {clicks}\n{shaking}{lost}",
+        elapsed / 60,
+        elapsed % 60
+    );
+}
+
+pub fn hide(hud: Query<(Entity,), With<Hud>>, mut commands: Commands) {
+    hud.for_each(|(e,)| commands.entity(e).despawn_recursive());
+}
@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::stages::GameStage;
+use crate::{minesweeper::ChainReactionEvent, stages::GameStage, stats::GameStats};
 
 #[derive(Debug, Component)]
 pub struct KillScreen;
@@ -45,11 +45,20 @@ pub fn draw(asset_server: Res<AssetServer>, mut commands: Commands) {
                     TextSection {
                         value: "Press R to restart".to_string(),
                         style: TextStyle {
-                            font,
+                            font: font.clone(),
                             font_size: 24.0,
                             color: Color::WHITE,
                         },
                     },
+                    // Filled in by `count_chain_reactions`
+                    TextSection {
+                        value: String::new(),
+                        style: TextStyle {
+                            font,
+                            font_size: 24.0,
+                            color: Color::ORANGE_RED,
+                        },
+                    },
                 ],
                 alignment: TextAlignment {
                     horizontal: HorizontalAlign::Center,
@@ -61,6 +70,21 @@ pub fn draw(asset_server: Res<AssetServer>, mut commands: Commands) {
         .insert(KillScreen);
 }
 
+pub fn count_chain_reactions(
+    mut events: EventReader<ChainReactionEvent>,
+    mut stats: ResMut<GameStats>,
+    mut kill_screen: Query<&mut Text, With<KillScreen>>,
+) {
+    let longest = match events.iter().map(|event| event.length).max() {
+        Some(length) if length > stats.longest_chain => length,
+        _ => return,
+    };
+    stats.longest_chain = longest;
+    for mut text in kill_screen.iter_mut() {
+        text.sections[2].value = format!("\nChain reaction of {longest} bombs");
+    }
+}
+
 pub fn hide(kill_screen: Query<(Entity,), With<KillScreen>>, mut commands: Commands) {
     kill_screen.for_each(|(e,)| commands.entity(e).despawn());
 }
@@ -10,16 +10,23 @@ use bevy_editor_pls::prelude::*;
 use bevy_inspector_egui::WorldInspectorPlugin;
 
 mod tile;
-use params::Params;
+use params::{Generation, Params};
 use stages::GameStage;
 use tile::Tile;
+mod arena;
+mod effects;
+mod hud;
 mod killscreen;
 mod map_actions;
 mod map_generator;
 mod minesweeper;
+mod numbers;
 mod params;
+mod solver;
 mod stages;
 mod startscreen;
+mod stats;
+mod stress;
 mod winscreen;
 
 fn main() {
@@ -39,7 +46,9 @@ fn main() {
     app.add_plugins(DefaultPickingPlugins);
     app.insert_resource(PickingPluginsState {
         enable_picking: true,
-        enable_highlighting: true,
+        // Tiles show whether they are flagged, hinted or revealed through their
+        // material, which highlighting would reset
+        enable_highlighting: false,
         enable_interacting: true,
         update_debug_cursor: false,
         print_debug_events: false,
@@ -57,10 +66,12 @@ fn main() {
         SystemSet::new()
             .label(SystemSets::GameplayControls)
             .with_system(zoom)
-            .with_system(map_actions::toggle_hint)
+            .with_system(map_actions::show_hint)
             .with_system(minesweeper::click_on_tile)
+            .with_system(minesweeper::flag_tile)
             .with_system(stages::trigger_endgame)
             .with_system(stages::trigger_reset)
+            .with_system(startscreen::choose_difficulty)
             .with_system(killscreen::end_game),
     );
     app.add_system_set(
@@ -75,19 +86,58 @@ fn main() {
             .label(SystemSets::Reactions)
             .after(SystemSets::Movements)
             .with_system(minesweeper::clear)
-            .with_system(minesweeper::go_nuclear_if_fast)
+            .with_system(stats::record_reveals.after(minesweeper::clear))
+            .with_system(minesweeper::stress_bombs)
+            .with_system(minesweeper::go_nuclear_if_fast.after(minesweeper::stress_bombs))
+            .with_system(minesweeper::show_bomb_danger)
+            .with_system(minesweeper::chain_reaction)
+            .with_system(minesweeper::blast_wave)
+            .with_system(arena::catch_escapees)
             .with_system(minesweeper::go_nuclear),
     );
+    app.add_system_set(
+        SystemSet::new()
+            .label(SystemSets::Effects)
+            .with_system(effects::drift)
+            .with_system(effects::fade_out)
+            .with_system(effects::flip_revealed),
+    );
+
+    // Despawned tiles only show up as removed once their commands have run
+    app.add_system_to_stage(CoreStage::PostUpdate, tile::forget_despawned);
 
     app.add_system_set(
         SystemSet::on_enter(GameStage::NewGame)
             .with_system(map_actions::create_map)
+            .with_system(arena::build_walls)
+            .with_system(startscreen::draw),
+    );
+    app.add_system_set(
+        SystemSet::new()
+            .with_run_criteria(startscreen::difficulty_changed)
+            .after(SystemSets::GameplayControls)
+            .with_system(map_actions::create_map)
+            .with_system(arena::build_walls)
+            .with_system(startscreen::hide)
             .with_system(startscreen::draw),
     );
     app.add_system_set(SystemSet::on_exit(GameStage::NewGame).with_system(startscreen::hide));
-    app.add_system_set(SystemSet::on_update(GameStage::MapSet).with_system(winscreen::you_win));
+    app.add_system_set(
+        SystemSet::on_enter(GameStage::MapSet)
+            .with_system(hud::start_clock)
+            .with_system(hud::draw),
+    );
+    app.add_system_set(
+        SystemSet::on_update(GameStage::MapSet)
+            .with_system(winscreen::you_win)
+            .with_system(hud::update),
+    );
+    app.add_system_set(SystemSet::on_exit(GameStage::MapSet).with_system(hud::hide));
 
     app.add_system_set(SystemSet::on_enter(GameStage::KillScreen).with_system(killscreen::draw));
+    app.add_system_set(
+        SystemSet::on_update(GameStage::KillScreen).with_system(killscreen::count_chain_reactions),
+    );
     app.add_system_set(SystemSet::on_exit(GameStage::KillScreen).with_system(killscreen::hide));
 
     app.add_system_set(SystemSet::on_enter(GameStage::WinScreen).with_system(winscreen::draw));
@@ -95,11 +145,38 @@ fn main() {
 
     app.add_event::<minesweeper::BoomEvent>();
     app.add_event::<minesweeper::ClearTileEvent>();
+    app.add_event::<minesweeper::TilesRevealedEvent>();
+    app.add_event::<minesweeper::ChainReactionEvent>();
     app.add_event::<killscreen::GameOverEvent>();
 
     app.register_type::<Tile>();
 
-    app.insert_resource(Params::regular());
+    app.init_resource::<map_actions::TileMaterials>();
+    app.init_resource::<stats::GameStats>();
+    app.init_resource::<tile::TileIndex>();
+    app.init_resource::<effects::ExplosionAssets>();
+    app.init_resource::<params::Difficulty>();
+    app.init_resource::<arena::ArenaBounds>();
+
+    // Bombs that build up stress instead of going off at a set speed with
+    // `MARMALADE_TRIGGER=stress`
+    let preset = match std::env::var("MARMALADE_TRIGGER").as_deref() {
+        Ok("stress") => Params::stressful(),
+        _ => Params::regular(),
+    };
+    app.insert_resource(Params {
+        // Replay a reported board with `MARMALADE_SEED=<seed from the logs>`
+        seed: std::env::var("MARMALADE_SEED")
+            .ok()
+            .and_then(|seed| seed.parse().ok()),
+        // Boards that never need a guess with `MARMALADE_NO_GUESS=1`, which
+        // takes a moment on the first click
+        generation: match std::env::var("MARMALADE_NO_GUESS").as_deref() {
+            Ok("1") => Generation::NoGuess { attempts: 50 },
+            _ => Generation::Random,
+        },
+        ..preset
+    });
 
     app.run();
 }
@@ -109,6 +186,7 @@ enum SystemSets {
     GameplayControls,
     Movements,
     Reactions,
+    Effects,
 }
 
 #[derive(Component)]
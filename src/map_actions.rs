@@ -1,57 +1,163 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashSet};
 use bevy_mod_picking::PickableBundle;
 use heron::prelude::*;
-use nalgebra_glm::smoothstep;
 
 use crate::{
-    map_generator::Map,
+    effects::Lifetime,
+    map_generator::{CellIndex, Coord, Map, MapError},
     minesweeper::Shrapnel,
-    params::Params,
-    tile::{Tile, TileKind},
+    numbers,
+    params::{Difficulty, Params},
+    solver::{self, Verdict},
+    stats::GameStats,
+    stress::Stress,
+    tile::{Tile, TileIndex, TileState},
 };
 
+pub struct TileMaterials {
+    pub hidden: Handle<StandardMaterial>,
+    pub flagged: Handle<StandardMaterial>,
+    pub questioned: Handle<StandardMaterial>,
+    pub safe_hint: Handle<StandardMaterial>,
+    pub bomb_hint: Handle<StandardMaterial>,
+    /// By number of bombs around a revealed tile, with the empty floor at 0
+    pub revealed: Vec<Handle<StandardMaterial>>,
+}
+
+impl FromWorld for TileMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let albedo: Handle<Image> = world
+            .get_resource::<AssetServer>()
+            .unwrap()
+            .load("graphics/TileAlbedo.png");
+        let number_textures: Vec<Handle<Image>> = {
+            let mut images = world.get_resource_mut::<Assets<Image>>().unwrap();
+            (1..=8).map(|n| images.add(numbers::texture(n))).collect()
+        };
+        let mut materials = world
+            .get_resource_mut::<Assets<StandardMaterial>>()
+            .unwrap();
+        let mut tile = |color: Color| {
+            materials.add(StandardMaterial {
+                base_color: color,
+                base_color_texture: Some(albedo.clone()),
+                ..Default::default()
+            })
+        };
+        let hidden = tile(Color::hsl(125., 0.5, 0.5));
+        let flagged = tile(Color::hsl(45., 0.9, 0.5));
+        let questioned = tile(Color::hsl(280., 0.4, 0.6));
+        let safe_hint = tile(Color::hsl(200., 0.7, 0.6));
+        let bomb_hint = tile(Color::hsl(15., 0.5, 0.5));
+
+        let floor = materials.add(StandardMaterial {
+            base_color: Color::hsl(125., 0.1, 0.2),
+            ..Default::default()
+        });
+        let revealed = std::iter::once(floor)
+            .chain(number_textures.into_iter().map(|texture| {
+                materials.add(StandardMaterial {
+                    base_color_texture: Some(texture),
+                    ..Default::default()
+                })
+            }))
+            .collect();
+
+        Self {
+            hidden,
+            flagged,
+            questioned,
+            safe_hint,
+            bomb_hint,
+            revealed,
+        }
+    }
+}
+
+impl TileMaterials {
+    /// How a tile looks without any hints
+    pub fn for_tile(&self, tile: &Tile) -> Handle<StandardMaterial> {
+        match (tile.state, tile.kind.number()) {
+            (TileState::Revealed, Some(n)) => self.revealed[n as usize].clone(),
+            (TileState::Flagged, _) => self.flagged.clone(),
+            (TileState::Questioned, _) => self.questioned.clone(),
+            _ => self.hidden.clone(),
+        }
+    }
+}
+
 pub fn create_map(
     params: Res<Params>,
+    difficulty: Res<Difficulty>,
+    materials: Res<TileMaterials>,
     asset_server: Res<AssetServer>,
-    old_entities: Query<(Entity,), Or<(With<Tile>, With<Shrapnel>)>>,
+    old_entities: Query<
+        (Entity,),
+        Or<(
+            With<Tile>,
+            With<Shrapnel>,
+            With<Lifetime>,
+            With<HintMessage>,
+            With<MapErrorMessage>,
+        )>,
+    >,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
+    // The new game is played by the chosen difficulty, whatever the last one
+    // ended with, see `stages::endgame`
+    let params = Params {
+        seed: params.seed,
+        generation: params.generation,
+        bomb_trigger: params.bomb_trigger,
+        ..difficulty.params()
+    };
     let mesh = meshes.add(Mesh::from(shape::Cube {
         size: params.block_size,
     }));
-    let albedo = asset_server.load("graphics/TileAlbedo.png");
-    let green_tile = materials.add(StandardMaterial {
-        base_color: Color::hsl(125., 0.5, 0.5),
-        base_color_texture: Some(albedo),
-        ..Default::default()
-    });
-    commands.insert_resource(Params::regular());
+    commands.insert_resource(GameStats::default());
 
     for (entity,) in old_entities.iter() {
         commands.entity(entity).despawn();
     }
 
-    let mut map = Map::new(params.map_rows, params.map_columns);
-    map.set_bombs(params.bomb_count);
+    // Bombs are only placed on the first click, see `minesweeper::click_on_tile`
+    let map = match Map::generate(&params) {
+        Ok(map) => map,
+        Err(error) => {
+            warn!("can't create map: {error}");
+            show_map_error(&mut commands, &asset_server, error);
+            // Everything that needs a map still gets one, just without tiles
+            commands.insert_resource(Map::with_seed(0, 0, 0));
+            commands.insert_resource(TileIndex::default());
+            commands.insert_resource(params);
+            return;
+        }
+    };
+    info!("creating map with seed {}", map.seed);
 
-    let blox = (params.map_rows * params.map_columns) as f32;
+    // Centered on the origin, with the first row at the top
+    let top_left = Vec3::new(
+        -(params.map_columns as f32 - 1.) * params.block_offset / 2.0,
+        (params.map_rows as f32 - 1.) * params.block_offset / 2.0,
+        0.0,
+    );
 
+    let mut tile_index = TileIndex::with_len(map.map.len());
     for (x, kind) in map.map.iter().enumerate() {
         let size = Vec3::new(params.block_size, params.block_size, 0.0);
-        let original_position = Vec3::new(
-            (x as f32 / blox.sqrt()).floor() * params.block_offset
-                - blox.sqrt() * params.block_offset / 2.0,
-            (x as f32 % blox.sqrt()).floor() * params.block_offset
-                - blox.sqrt() * params.block_offset / 2.0,
-            0.0,
-        );
-
-        commands
+        let Coord { row, col } = map.index_to_coord(CellIndex(x)).unwrap();
+        let original_position = top_left
+            + Vec3::new(
+                col as f32 * params.block_offset,
+                -(row as f32) * params.block_offset,
+                0.0,
+            );
+
+        let entity = commands
             .spawn_bundle(PbrBundle {
                 mesh: mesh.clone(),
-                material: green_tile.clone(),
+                material: materials.hidden.clone(),
                 transform: Transform::from_translation(original_position),
                 ..Default::default()
             })
@@ -61,90 +167,157 @@ pub fn create_map(
                 border_radius: None,
             })
             .insert(Velocity::from(Vec2::ZERO))
-            .insert(match kind {
-                TileKind::Boom => PhysicMaterial {
-                    restitution: 0.2,
-                    friction: 0.2,
-                    density: 10.,
-                },
-                TileKind::Danger(x) => PhysicMaterial {
-                    restitution: 0.9,
-                    friction: 0.2,
-                    density: 1. + 2. * smoothstep(1., 8., *x as f32),
-                },
-                TileKind::Fine => PhysicMaterial {
-                    restitution: 0.9,
-                    friction: 0.2,
-                    density: 1.,
-                },
-                TileKind::Defused(x) => PhysicMaterial {
-                    restitution: 0.9,
-                    friction: 0.2,
-                    density: 1. + 2. * smoothstep(1., 8., *x as f32),
-                },
-            })
+            .insert(kind.physic_material())
             .insert(Damping::from_linear(0.1).with_angular(0.9))
             .insert(Tile {
                 original_position,
                 kind: *kind,
                 index_in_map: x,
+                state: TileState::Hidden,
             })
+            .insert(Stress::default())
             .insert_bundle(PickableBundle::default())
-            .insert(Name::new(format!("My block {x}")));
+            .insert(Name::new(format!("My block {x}")))
+            .id();
+        tile_index.insert(x, entity);
     }
 
+    commands.insert_resource(tile_index);
     commands.insert_resource(map);
+    commands.insert_resource(params);
 }
 
-pub fn toggle_hint(
-    asset_server: Res<AssetServer>,
+#[derive(Debug, Component)]
+pub struct MapErrorMessage;
+
+fn show_map_error(commands: &mut Commands, asset_server: &AssetServer, error: MapError) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                align_self: AlignSelf::Center,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(15.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                format!("Can't make this board: {error}"),
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 32.0,
+                    color: Color::ORANGE_RED,
+                },
+                TextAlignment::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(MapErrorMessage);
+}
+
+/// Tile currently highlighted by a hint
+#[derive(Debug, Component)]
+pub struct Hinted;
+
+#[derive(Debug, Component)]
+pub struct HintMessage;
+
+/// Point out one tile the player can prove to be safe, or else one they can
+/// prove to be a bomb, based on what they can currently see
+#[allow(clippy::too_many_arguments)]
+pub fn show_hint(
     keys: Res<Input<KeyCode>>,
-    mut params: ResMut<Params>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut query: Query<(&Tile, &mut Handle<StandardMaterial>)>,
+    map: Res<Map>,
+    materials: Res<TileMaterials>,
+    asset_server: Res<AssetServer>,
+    mut stats: ResMut<GameStats>,
+    tile_index: Res<TileIndex>,
+    mut tiles: Query<(
+        Entity,
+        &Tile,
+        &mut Handle<StandardMaterial>,
+        Option<&Hinted>,
+    )>,
+    old_messages: Query<(Entity,), With<HintMessage>>,
+    mut commands: Commands,
 ) {
     if !keys.just_pressed(KeyCode::H) {
         return;
     }
-    params.hint = !params.hint;
-
-    let albedo = asset_server.load("graphics/TileAlbedo.png");
-
-    let green_tile = materials.add(StandardMaterial {
-        base_color: Color::hsl(125., 0.5, 0.5),
-        base_color_texture: Some(albedo.clone()),
-        ..Default::default()
-    });
-    let red_tile = materials.add(StandardMaterial {
-        base_color: Color::hsl(15., 0.5, 0.5),
-        base_color_texture: Some(albedo.clone()),
-        ..Default::default()
-    });
-    let orange_tile = materials.add(StandardMaterial {
-        base_color: Color::hsl(55., 0.5, 0.5),
-        base_color_texture: Some(albedo),
-        ..Default::default()
-    });
-
-    for (tile, mut material) in query.iter_mut() {
-        if !params.hint {
-            *material = green_tile.clone();
-            continue;
+
+    for (entity, tile, mut material, hinted) in tiles.iter_mut() {
+        if hinted.is_some() {
+            *material = materials.for_tile(tile);
+            commands.entity(entity).remove::<Hinted>();
         }
+    }
+    for (entity,) in old_messages.iter() {
+        commands.entity(entity).despawn();
+    }
 
-        match tile.kind {
-            TileKind::Boom => {
-                *material = red_tile.clone();
-            }
-            TileKind::Danger(_) => {
-                *material = orange_tile.clone();
-            }
-            TileKind::Defused(_) => {
-                *material = green_tile.clone();
-            }
-            TileKind::Fine => {
-                *material = green_tile.clone();
+    let message = if map.bombs == 0 {
+        "The first click is always safe".to_string()
+    } else {
+        let tiles_in_state =
+            |state| -> HashSet<usize> { map.tiles_in_state(state).map(|index| index.0).collect() };
+        let revealed = tiles_in_state(TileState::Revealed);
+        let flagged = tiles_in_state(TileState::Flagged);
+
+        // Lost tiles are as unknown as any hidden one, but not worth a hint
+        let deductions: Vec<_> = solver::deduce(&map, &revealed, &flagged)
+            .into_iter()
+            .filter(|d| map.state(CellIndex(d.index)) != Some(TileState::Lost))
+            .collect();
+        let hint = deductions
+            .iter()
+            .find(|d| d.verdict == Verdict::Safe)
+            .or_else(|| deductions.first());
+
+        match hint {
+            Some(deduction) => {
+                stats.hints += 1;
+                let target = tile_index
+                    .entity(deduction.index)
+                    .and_then(|entity| tiles.get_mut(entity).ok());
+                if let Some((entity, _, mut material, _)) = target {
+                    *material = match deduction.verdict {
+                        Verdict::Safe => materials.safe_hint.clone(),
+                        Verdict::Bomb => materials.bomb_hint.clone(),
+                    };
+                    commands.entity(entity).insert(Hinted);
+                }
+                deduction.explain(&map)
             }
+            None => "Nothing can be deduced for sure, you'll have to guess".to_string(),
         }
-    }
+    };
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                align_self: AlignSelf::FlexEnd,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(15.0),
+                    left: Val::Px(15.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                message,
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 24.0,
+                    color: Color::WHITE,
+                },
+                TextAlignment {
+                    horizontal: HorizontalAlign::Left,
+                    ..Default::default()
+                },
+            ),
+            ..Default::default()
+        })
+        .insert(HintMessage);
 }
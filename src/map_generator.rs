@@ -1,104 +1,316 @@
-use crate::tile::TileKind;
+use std::fmt;
+
+use crate::{
+    params::Params,
+    solver,
+    tile::{TileKind, TileState},
+};
 use bevy::prelude::*;
-use rand::{thread_rng, Rng};
+use rand::{rngs::StdRng, seq::SliceRandom, thread_rng, Rng, SeedableRng};
+
+/// Tiles solved over all attempts of [`Map::set_bombs_without_guessing`].
+///
+/// That happens on the first click, and solving takes around a microsecond
+/// per tile, so this keeps the click from stalling the game on big boards.
+const NO_GUESS_BUDGET: usize = 20_000;
+
+/// Where a tile sits on the board, counting from the top left
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Coord {
+    pub row: usize,
+    pub col: usize,
+}
+
+impl Coord {
+    pub fn new(row: usize, col: usize) -> Self {
+        Self { row, col }
+    }
+
+    /// `None` if that would take us past the top or left edge, the other
+    /// edges depend on the map and are checked by [`Map::coord_to_index`]
+    pub fn offset(self, rows: isize, cols: isize) -> Option<Self> {
+        let offset = |at: usize, by: isize| {
+            if by < 0 {
+                at.checked_sub(by.unsigned_abs())
+            } else {
+                at.checked_add(by as usize)
+            }
+        };
+        Some(Self {
+            row: offset(self.row, rows)?,
+            col: offset(self.col, cols)?,
+        })
+    }
+}
+
+/// Where a tile is stored in [`Map::map`], which goes row by row
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CellIndex(pub usize);
+
+/// Why a board can't be made from the given [`Params`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    ZeroSized {
+        rows: usize,
+        columns: usize,
+    },
+    TooManyBombs {
+        bombs: usize,
+        tiles: usize,
+    },
+    /// The first click and its neighbors are kept clear, so they need room
+    NoRoomForFirstClick {
+        bombs: usize,
+        room: usize,
+    },
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::ZeroSized { rows, columns } => {
+                write!(f, "a {rows}x{columns} board has no tiles to play on")
+            }
+            MapError::TooManyBombs { bombs, tiles } => {
+                write!(f, "{bombs} bombs don't fit on a board of {tiles} tiles")
+            }
+            MapError::NoRoomForFirstClick { bombs, room } => write!(
+                f,
+                "{bombs} bombs leave no room for a safe first click, at most {room} do"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
 
-#[derive(Component, Default, Debug)]
+#[derive(Component, Debug)]
 pub struct Map {
     pub bombs: usize,
     pub height: usize,
     pub width: usize,
+    pub seed: u64,
     pub map: Vec<TileKind>,
+    /// Goes along with `map`, see [`Map::set_state`]
+    states: Vec<TileState>,
+    revealed_safe: usize,
+    /// Safe tiles that left the arena, which the player can't reveal anymore
+    lost_safe: usize,
+    rng: StdRng,
 }
 
 impl Map {
+    /// An empty board as described by `params`, ready for bombs to be placed
+    /// around the first click
+    pub fn generate(params: &Params) -> Result<Self, MapError> {
+        let (rows, columns, bombs) = (params.map_rows, params.map_columns, params.bomb_count);
+        let tiles = rows * columns;
+        if tiles == 0 {
+            return Err(MapError::ZeroSized { rows, columns });
+        }
+        if bombs >= tiles {
+            return Err(MapError::TooManyBombs { bombs, tiles });
+        }
+        // Wherever the first click lands, it keeps at most a 3x3 square clear
+        let room = tiles - rows.min(3) * columns.min(3);
+        if bombs > room {
+            return Err(MapError::NoRoomForFirstClick { bombs, room });
+        }
+
+        let seed = params.seed.unwrap_or_else(|| thread_rng().gen());
+        Ok(Self::with_seed(rows, columns, seed))
+    }
+
+    /// Games go through [`Map::generate`], which checks the parameters first
+    #[cfg(test)]
     pub fn new(height: usize, width: usize) -> Self {
+        Self::with_seed(height, width, thread_rng().gen())
+    }
+
+    /// Same seed and dimensions give the same bombs, as long as everything
+    /// random about a session is drawn from [`Map::rng`].
+    pub fn with_seed(height: usize, width: usize, seed: u64) -> Self {
         let map = (0..height * width).map(|_| TileKind::Fine).collect();
 
         Self {
             bombs: 0,
             height,
             width,
+            seed,
             map,
+            states: vec![TileState::Hidden; height * width],
+            revealed_safe: 0,
+            lost_safe: 0,
+            rng: StdRng::seed_from_u64(seed),
         }
     }
 
-    pub fn index_to_coord(&self, index: usize) -> Option<(usize, usize)> {
-        let len = self.map.len();
-        if index > len {
-            warn!("tried to fetch coords for index {index} in map of length {len}");
-            return None;
-        }
-        let (x, y) = (index / self.width, index % self.width);
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
 
-        Some((x, y))
+    pub fn state(&self, index: CellIndex) -> Option<TileState> {
+        self.states.get(index.0).copied()
     }
 
-    pub fn coord_to_index(&self, (x, y): (usize, usize)) -> Option<usize> {
-        // let (x, y) = (row - 1, column - 1);
-        let width = self.width;
-        if x > self.width {
-            warn!(
-                "tried to fetch index for coords {x},{y} but {x} is greater than map width {width}"
-            );
-            return None;
+    /// Every change to what the player knows about a tile goes through here.
+    /// Systems use [`crate::tile::Tile::set_state`] instead, which keeps the
+    /// tile in step.
+    ///
+    /// Returns `false` and leaves the tile alone if it can't go from its
+    /// current state to `next`, see [`TileState::can_become`]. On top of
+    /// that, only bombs explode and only everything else can be revealed.
+    pub fn set_state(&mut self, index: CellIndex, next: TileState) -> bool {
+        let current = match self.state(index) {
+            Some(current) => current,
+            None => return false,
+        };
+        let is_bomb = self.map[index.0] == TileKind::Boom;
+        let allowed = current.can_become(next)
+            && match next {
+                TileState::Revealed => !is_bomb,
+                TileState::Exploded => is_bomb,
+                _ => true,
+            };
+        if !allowed {
+            return false;
         }
-        let height = self.height;
-        if x > self.height {
-            warn!("tried to fetch index for coords {x},{y} but {x} is greater than map height {height}");
-            return None;
+
+        self.states[index.0] = next;
+        match next {
+            TileState::Revealed => self.revealed_safe += 1,
+            TileState::Lost if !is_bomb => self.lost_safe += 1,
+            _ => {}
         }
+        true
+    }
 
-        let index = x * width + y;
+    /// Indices of all tiles in `state`
+    pub fn tiles_in_state(&self, state: TileState) -> impl Iterator<Item = CellIndex> + '_ {
+        (0..self.states.len())
+            .filter(move |index| self.states[*index] == state)
+            .map(CellIndex)
+    }
+
+    #[cfg(test)]
+    pub fn revealed_safe(&self) -> usize {
+        self.revealed_safe
+    }
+
+    pub fn safe_tiles(&self) -> usize {
+        self.map.len() - self.bombs
+    }
 
-        let len = self.map.len();
-        if index > len {
-            warn!("tried to fetch index for {x},{y} in map of length {len} but calculated index {index} is out of bounds");
+    /// Whether every tile that isn't a bomb has been revealed, or is lost and
+    /// can't be anymore
+    pub fn is_cleared(&self) -> bool {
+        self.revealed_safe > 0 && self.revealed_safe + self.lost_safe == self.safe_tiles()
+    }
+
+    pub fn index_to_coord(&self, index: CellIndex) -> Option<Coord> {
+        if index.0 >= self.map.len() {
             return None;
         }
-
-        Some(index)
+        Some(Coord::new(index.0 / self.width, index.0 % self.width))
     }
 
-    pub fn at_coords(&self, coords: (usize, usize)) -> Option<TileKind> {
-        let index = self.coord_to_index(coords)?;
-        self.map.get(index).copied()
+    pub fn coord_to_index(&self, coord: Coord) -> Option<CellIndex> {
+        if coord.row >= self.height || coord.col >= self.width {
+            return None;
+        }
+        Some(CellIndex(coord.row * self.width + coord.col))
     }
 
+    /// Bombs anywhere, games always keep the first click clear
+    #[cfg(test)]
     pub fn set_bombs(&mut self, count: usize) {
-        self.bombs = count;
-        let mut remaining_bombs = count;
-        let mut rng = thread_rng();
-        // Place bombs
-        while remaining_bombs > 0 {
-            let (x, y) = (
-                rng.gen_range(0..self.width) as usize,
-                rng.gen_range(0..self.height) as usize,
-            );
-
-            if let Some(x @ TileKind::Fine) = self.map.get_mut(y * self.width + x) {
-                *x = TileKind::Boom;
-                remaining_bombs -= 1;
+        self.place_bombs(count, &[]);
+    }
+
+    /// Place bombs everywhere but on `index` and its neighbors, so that the
+    /// first click is safe and opens up some room to work with.
+    pub fn set_bombs_around(&mut self, count: usize, index: CellIndex) {
+        let mut keep_clear: Vec<usize> =
+            self.neighbors(index).map(|(_, index, _)| index.0).collect();
+        keep_clear.push(index.0);
+
+        self.place_bombs(count, &keep_clear);
+    }
+
+    /// Like [`Map::set_bombs_around`], but tries up to `attempts` layouts to
+    /// find one that can be cleared from `index` without ever guessing.
+    /// Bigger boards get fewer attempts, see [`NO_GUESS_BUDGET`].
+    ///
+    /// Returns `false` if no such layout was found, in which case the map
+    /// keeps the last (random) layout.
+    pub fn set_bombs_without_guessing(
+        &mut self,
+        count: usize,
+        index: CellIndex,
+        attempts: usize,
+    ) -> bool {
+        let attempts = attempts.min(NO_GUESS_BUDGET / self.map.len().max(1)).max(1);
+        for _ in 0..attempts {
+            self.set_bombs_around(count, index);
+            if solver::solvable_from(self, index.0) {
+                return true;
             }
         }
+        false
+    }
+
+    /// Never places more bombs than there is room for, [`Map::generate`]
+    /// makes sure that doesn't come up in a game
+    fn place_bombs(&mut self, count: usize, keep_clear: &[usize]) {
+        self.map.iter_mut().for_each(|tile| *tile = TileKind::Fine);
+        let candidates: Vec<usize> = (0..self.map.len())
+            .filter(|index| !keep_clear.contains(index))
+            .collect();
+        let bombs: Vec<usize> = candidates
+            .choose_multiple(&mut self.rng, count)
+            .copied()
+            .collect();
+
+        self.bombs = bombs.len();
+        for index in bombs {
+            self.map[index] = TileKind::Boom;
+        }
+
+        self.set_numbers();
+    }
 
+    /// A map with bombs exactly where we want them
+    #[cfg(test)]
+    pub fn with_bombs(height: usize, width: usize, bombs: &[usize]) -> Self {
+        let mut map = Self::with_seed(height, width, 0);
+        map.bombs = bombs.len();
+        for index in bombs {
+            map.map[*index] = TileKind::Boom;
+        }
+        map.set_numbers();
+        map
+    }
+
+    fn set_numbers(&mut self) {
         // Place bomb neighbors
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let idx = self.coord_to_index((x, y)).unwrap();
-                if self.map[idx] == TileKind::Boom {
-                    continue;
-                }
-                let num = self.bomb_count_at(idx);
-                if num > 0 {
-                    self.map[idx] =
-                        TileKind::Danger(num.try_into().expect("more than 8 bombs around me? wow"));
-                }
+        for idx in 0..self.map.len() {
+            if self.map[idx] == TileKind::Boom {
+                continue;
+            }
+            let num = self.bomb_count_at(idx);
+            if num > 0 {
+                self.map[idx] =
+                    TileKind::Danger(num.try_into().expect("more than 8 bombs around me? wow"));
             }
         }
     }
 
-    pub fn neighbors(&self, index: usize) -> Vec<((usize, usize), usize, TileKind)> {
-        let around = vec![
+    /// Up to 8 tiles around `index`, none if `index` isn't on the map
+    pub fn neighbors(
+        &self,
+        index: CellIndex,
+    ) -> impl Iterator<Item = (Coord, CellIndex, TileKind)> + '_ {
+        const AROUND: [(isize, isize); 8] = [
             (-1, -1),
             (-1, 0),
             (-1, 1),
@@ -108,37 +320,71 @@ impl Map {
             (1, 0),
             (1, 1),
         ];
-        let (target_x, target_y) = self.index_to_coord(index).unwrap();
+        let target = self.index_to_coord(index);
 
-        around
+        AROUND.into_iter().filter_map(move |(rows, cols)| {
+            let coord = target?.offset(rows, cols)?;
+            let index = self.coord_to_index(coord)?;
+            Some((coord, index, self.map[index.0]))
+        })
+    }
+
+    /// Tiles a click on `start` uncovers: `start` itself and, if there are no
+    /// bombs around it, the whole empty area it is part of along with the
+    /// numbers around that. Tiles `is_hidden` turns down are left alone.
+    pub fn flood_fill(
+        &self,
+        start: CellIndex,
+        is_hidden: impl Fn(CellIndex) -> bool,
+    ) -> Vec<CellIndex> {
+        self.flood_fill_by_distance(start, is_hidden)
             .into_iter()
-            .filter_map(|(offset_x, offset_y)| {
-                let x = match (target_x as isize).checked_add(offset_x) {
-                    None => return None,
-                    Some(x) if x as usize > self.width - 1 => return None,
-                    Some(x) => x as usize,
-                };
-                let y = match (target_y as isize).checked_add(offset_y) {
-                    None => return None,
-                    Some(y) if y as usize > self.height - 1 => return None,
-                    Some(y) => y as usize,
-                };
-
-                Some((x, y))
-            })
-            .map(|coords| {
-                (
-                    coords,
-                    self.coord_to_index(coords).unwrap(),
-                    self.at_coords(coords).unwrap(),
-                )
-            })
+            .map(|(index, _)| index)
             .collect()
     }
 
+    /// Same as [`Map::flood_fill`], along with how many steps away from
+    /// `start` each tile was reached, closest first
+    pub fn flood_fill_by_distance(
+        &self,
+        start: CellIndex,
+        is_hidden: impl Fn(CellIndex) -> bool,
+    ) -> Vec<(CellIndex, usize)> {
+        let mut opened = Vec::new();
+        if start.0 >= self.map.len() || !is_hidden(start) {
+            return opened;
+        }
+        let mut seen = vec![false; self.map.len()];
+        seen[start.0] = true;
+        opened.push((start, 0));
+
+        // `opened` doubles as the queue, everything before `next` is done
+        let mut next = 0;
+        while let Some(&(CellIndex(index), distance)) = opened.get(next) {
+            next += 1;
+            // Only empty tiles open up their neighbors
+            if self.map[index] != TileKind::Fine {
+                continue;
+            }
+            // Same as `neighbors`, but this is hot enough on big boards to
+            // be worth working with plain indices and skipping the conversions
+            let (row, col) = (index / self.width, index % self.width);
+            for row in row.saturating_sub(1)..(row + 2).min(self.height) {
+                let first = row * self.width + col.saturating_sub(1);
+                let last = row * self.width + (col + 2).min(self.width);
+                for (neighbor, seen) in (first..).zip(&mut seen[first..last]) {
+                    if !*seen && is_hidden(CellIndex(neighbor)) {
+                        *seen = true;
+                        opened.push((CellIndex(neighbor), distance + 1));
+                    }
+                }
+            }
+        }
+        opened
+    }
+
     fn bomb_count_at(&self, index: usize) -> usize {
-        self.neighbors(index)
-            .iter()
+        self.neighbors(CellIndex(index))
             .filter(|(_, _, tile)| *tile == TileKind::Boom)
             .count()
     }
@@ -147,19 +393,24 @@ impl Map {
 #[test]
 fn test_index_to_coord() {
     let map = Map::new(8, 8);
-    assert_eq!(map.index_to_coord(0), Some((0, 0)));
-    assert_eq!(map.index_to_coord(1), Some((0, 1)));
-    assert_eq!(map.index_to_coord(8), Some((1, 0)));
-    assert_eq!(map.index_to_coord(63), Some((7, 7)));
+    let coord = |index| map.index_to_coord(CellIndex(index));
+    assert_eq!(coord(0), Some(Coord::new(0, 0)));
+    assert_eq!(coord(1), Some(Coord::new(0, 1)));
+    assert_eq!(coord(8), Some(Coord::new(1, 0)));
+    assert_eq!(coord(63), Some(Coord::new(7, 7)));
+    assert_eq!(coord(64), None);
 }
 
 #[test]
 fn test_coord_to_index() {
     let map = Map::new(8, 8);
-    assert_eq!(map.coord_to_index((0, 0)), Some(0));
-    assert_eq!(map.coord_to_index((0, 1)), Some(1));
-    assert_eq!(map.coord_to_index((1, 0)), Some(8));
-    assert_eq!(map.coord_to_index((7, 7)), Some(63));
+    let index = |row, col| map.coord_to_index(Coord::new(row, col));
+    assert_eq!(index(0, 0), Some(CellIndex(0)));
+    assert_eq!(index(0, 1), Some(CellIndex(1)));
+    assert_eq!(index(1, 0), Some(CellIndex(8)));
+    assert_eq!(index(7, 7), Some(CellIndex(63)));
+    assert_eq!(index(8, 0), None);
+    assert_eq!(index(0, 8), None);
 }
 
 #[test]
@@ -174,3 +425,322 @@ fn test_map() {
             > 0
     );
 }
+
+#[test]
+fn test_same_seed_same_map() {
+    let mut a = Map::with_seed(12, 12, 1337);
+    let mut b = Map::with_seed(12, 12, 1337);
+    a.set_bombs(30);
+    b.set_bombs(30);
+    assert_eq!(a.map, b.map);
+
+    let mut c = Map::with_seed(12, 12, 7331);
+    c.set_bombs(30);
+    assert_ne!(a.map, c.map);
+}
+
+#[test]
+fn test_first_click_is_safe() {
+    for seed in 0..50 {
+        let mut map = Map::with_seed(8, 8, seed);
+        map.set_bombs_around(40, CellIndex(27));
+        assert_eq!(map.map[27], TileKind::Fine);
+        assert!(map
+            .neighbors(CellIndex(27))
+            .all(|(_, _, kind)| kind != TileKind::Boom));
+        assert_eq!(map.map.iter().filter(|x| **x == TileKind::Boom).count(), 40);
+    }
+}
+
+#[test]
+fn test_no_guessing_needed() {
+    let mut solved = 0;
+    for seed in 0..10 {
+        let mut map = Map::with_seed(16, 16, seed);
+        if map.set_bombs_without_guessing(40, CellIndex(100), 50) {
+            assert!(solver::solvable_from(&map, 100));
+            solved += 1;
+        }
+        assert_eq!(map.map.iter().filter(|x| **x == TileKind::Boom).count(), 40);
+    }
+    assert!(solved > 7, "only {solved} of 10 boards were solvable");
+}
+
+#[test]
+fn test_cleared() {
+    let mut map = Map::with_bombs(3, 3, &[0, 8]);
+    for index in 1..8 {
+        assert!(!map.is_cleared());
+        assert!(map.set_state(CellIndex(index), TileState::Revealed));
+    }
+    assert!(!map.set_state(CellIndex(1), TileState::Revealed));
+    assert_eq!(map.revealed_safe(), 7);
+    assert!(map.is_cleared());
+
+    // Uncovering a bomb doesn't get you any closer
+    let mut map = Map::with_bombs(2, 2, &[0]);
+    assert!(map.set_state(CellIndex(0), TileState::Exploded));
+    assert_eq!(map.revealed_safe(), 0);
+
+    // Lost tiles don't have to be revealed, but don't count as revealed either
+    assert!(map.set_state(CellIndex(1), TileState::Lost));
+    assert!(map.set_state(CellIndex(2), TileState::Revealed));
+    assert!(!map.is_cleared());
+    assert!(!map.set_state(CellIndex(1), TileState::Revealed));
+    assert!(map.set_state(CellIndex(3), TileState::Revealed));
+    assert_eq!(map.revealed_safe(), 2);
+    assert!(map.is_cleared());
+}
+
+#[test]
+fn test_state_transitions() {
+    let mut map = Map::with_bombs(2, 2, &[0]);
+
+    // Right clicks go round in circles
+    assert!(map.set_state(CellIndex(1), TileState::Flagged));
+    assert!(!map.set_state(CellIndex(1), TileState::Revealed));
+    assert!(map.set_state(CellIndex(1), TileState::Questioned));
+    assert!(map.set_state(CellIndex(1), TileState::Hidden));
+    assert!(!map.set_state(CellIndex(1), TileState::Questioned));
+    assert_eq!(map.tiles_in_state(TileState::Hidden).count(), 4);
+
+    // Questioned tiles can still be revealed, but revealed is for good
+    assert!(map.set_state(CellIndex(1), TileState::Flagged));
+    assert!(map.set_state(CellIndex(1), TileState::Questioned));
+    assert!(map.set_state(CellIndex(1), TileState::Revealed));
+    assert!(!map.set_state(CellIndex(1), TileState::Flagged));
+    assert!(!map.set_state(CellIndex(1), TileState::Hidden));
+
+    // Bombs explode instead of being revealed, and only bombs explode
+    assert!(!map.set_state(CellIndex(0), TileState::Revealed));
+    assert!(!map.set_state(CellIndex(2), TileState::Exploded));
+    assert!(map.set_state(CellIndex(0), TileState::Exploded));
+    assert!(!map.set_state(CellIndex(0), TileState::Hidden));
+    assert_eq!(map.state(CellIndex(0)), Some(TileState::Exploded));
+    assert_eq!(map.state(CellIndex(4)), None);
+    assert!(!map.set_state(CellIndex(4), TileState::Flagged));
+}
+
+#[test]
+fn test_rectangular_coords() {
+    // Expert boards are 16 rows of 30 tiles
+    let map = Map::new(16, 30);
+    let coord = |index| map.index_to_coord(CellIndex(index));
+    assert_eq!(coord(29), Some(Coord::new(0, 29)));
+    assert_eq!(coord(30), Some(Coord::new(1, 0)));
+    assert_eq!(coord(479), Some(Coord::new(15, 29)));
+    assert_eq!(coord(480), None);
+    let index = |row, col| map.coord_to_index(Coord::new(row, col));
+    assert_eq!(index(15, 29), Some(CellIndex(479)));
+    assert_eq!(index(16, 0), None);
+    assert_eq!(index(0, 30), None);
+}
+
+#[test]
+fn test_coord_round_trip() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..200 {
+        let (height, width) = (rng.gen_range(1..64), rng.gen_range(1..64));
+        let map = Map::new(height, width);
+
+        for _ in 0..50 {
+            let index = CellIndex(rng.gen_range(0..height * width));
+            let coord = map.index_to_coord(index).unwrap();
+            assert!(coord.row < height && coord.col < width);
+            assert_eq!(map.coord_to_index(coord), Some(index));
+
+            let coord = Coord::new(rng.gen_range(0..height), rng.gen_range(0..width));
+            let index = map.coord_to_index(coord).unwrap();
+            assert_eq!(map.index_to_coord(index), Some(coord));
+        }
+
+        // Anything off the board is turned away rather than wrapped around
+        let outside = CellIndex(rng.gen_range(height * width..height * width * 2));
+        assert_eq!(map.index_to_coord(outside), None);
+        let below = Coord::new(rng.gen_range(height..height * 2), rng.gen_range(0..width));
+        assert_eq!(map.coord_to_index(below), None);
+        let right = Coord::new(rng.gen_range(0..height), rng.gen_range(width..width * 2));
+        assert_eq!(map.coord_to_index(right), None);
+    }
+}
+
+#[test]
+fn test_rectangular_neighbors() {
+    let map = Map::new(16, 30);
+    let neighbors = |index| {
+        let mut neighbors: Vec<usize> = map
+            .neighbors(CellIndex(index))
+            .map(|(_, i, _)| i.0)
+            .collect();
+        neighbors.sort_unstable();
+        neighbors
+    };
+    assert_eq!(neighbors(0), vec![1, 30, 31]);
+    assert_eq!(neighbors(29), vec![28, 58, 59]);
+    assert_eq!(neighbors(450), vec![420, 421, 451]);
+    assert_eq!(neighbors(479), vec![448, 449, 478]);
+    assert_eq!(neighbors(45), vec![14, 15, 16, 44, 46, 74, 75, 76]);
+}
+
+#[test]
+fn test_rectangular_numbers() {
+    for (height, width) in [(16, 30), (30, 16)] {
+        let mut map = Map::with_seed(height, width, 3);
+        map.set_bombs_around(99, CellIndex(100));
+        assert_eq!(map.map.iter().filter(|x| **x == TileKind::Boom).count(), 99);
+        for index in 0..map.map.len() {
+            let bombs = map
+                .neighbors(CellIndex(index))
+                .filter(|(_, _, kind)| *kind == TileKind::Boom)
+                .count() as u8;
+            match map.map[index] {
+                TileKind::Boom => {}
+                TileKind::Fine => assert_eq!(bombs, 0),
+                TileKind::Danger(n) => assert_eq!(bombs, n),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_generate() {
+    let params = |rows, columns, bomb_count| {
+        let mut params = Params::regular();
+        params.map_rows = rows;
+        params.map_columns = columns;
+        params.bomb_count = bomb_count;
+        params.seed = Some(1);
+        params
+    };
+
+    let map = Map::generate(&params(16, 30, 99)).unwrap();
+    assert_eq!((map.height, map.width, map.seed), (16, 30, 1));
+
+    assert_eq!(
+        Map::generate(&params(0, 30, 0)).unwrap_err(),
+        MapError::ZeroSized {
+            rows: 0,
+            columns: 30
+        }
+    );
+    assert_eq!(
+        Map::generate(&params(3, 3, 9)).unwrap_err(),
+        MapError::TooManyBombs { bombs: 9, tiles: 9 }
+    );
+    assert_eq!(
+        Map::generate(&params(3, 3, 1)).unwrap_err(),
+        MapError::NoRoomForFirstClick { bombs: 1, room: 0 }
+    );
+    assert_eq!(
+        Map::generate(&params(1, 10, 8)).unwrap_err(),
+        MapError::NoRoomForFirstClick { bombs: 8, room: 7 }
+    );
+    assert!(Map::generate(&params(1, 10, 7)).is_ok());
+}
+
+#[test]
+fn test_difficulty_boards() {
+    use crate::params::Difficulty;
+
+    for difficulty in [
+        Difficulty::Beginner,
+        Difficulty::Intermediate,
+        Difficulty::Expert,
+    ] {
+        let (rows, columns, bombs) = difficulty.board();
+        let mut map = Map::generate(&difficulty.params()).unwrap();
+        assert_eq!((map.height, map.width), (rows, columns));
+        map.set_bombs_around(bombs, CellIndex(0));
+        assert_eq!(map.bombs, bombs);
+    }
+
+    // The smallest and biggest custom boards, see `Difficulty::resized`
+    let tiny = Difficulty::Beginner.resized(-20, -20, -20);
+    assert!(Map::generate(&tiny.params()).is_err());
+    let huge = Difficulty::Expert.resized(1000, 1000, 10_000);
+    assert!(Map::generate(&huge.params()).is_ok());
+}
+
+#[test]
+fn test_crowded_board() {
+    // As many bombs as there is room for still ends, with every one placed
+    let mut map = Map::with_seed(9, 9, 0);
+    map.set_bombs_around(72, CellIndex(40));
+    assert_eq!(map.bombs, 72);
+    assert_eq!(map.map.iter().filter(|x| **x == TileKind::Boom).count(), 72);
+}
+
+#[test]
+fn test_flood_fill() {
+    // . 2 * 2 .
+    // . 3 * 3 .
+    // . 3 * 3 .   <- nothing right of the bombs is reached
+    // . 3 * 3 .
+    // . 2 * 2 .
+    let map = Map::with_bombs(5, 5, &[2, 7, 12, 17, 22]);
+    let opened = |start, hidden: &dyn Fn(usize) -> bool| {
+        let mut opened: Vec<usize> = map
+            .flood_fill(CellIndex(start), |index| hidden(index.0))
+            .into_iter()
+            .map(|index| index.0)
+            .collect();
+        opened.sort_unstable();
+        opened
+    };
+    assert_eq!(
+        opened(0, &|_| true),
+        vec![0, 1, 5, 6, 10, 11, 15, 16, 20, 21]
+    );
+
+    // Numbers only uncover themselves
+    assert_eq!(opened(1, &|_| true), vec![1]);
+
+    // Nor does it go past tiles that aren't hidden anymore
+    assert_eq!(opened(0, &|index| index != 5), vec![0, 1, 6]);
+    assert!(opened(0, &|index| index != 0).is_empty());
+}
+
+#[test]
+fn test_flood_fill_by_distance() {
+    // Same board as above, going down the left column from the top
+    let map = Map::with_bombs(5, 5, &[2, 7, 12, 17, 22]);
+    let opened = map.flood_fill_by_distance(CellIndex(0), |_| true);
+    assert_eq!(opened[0], (CellIndex(0), 0));
+    assert!(opened.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+
+    let distance = |index| opened.iter().find(|(at, _)| at.0 == index).unwrap().1;
+    assert_eq!(distance(1), 1);
+    assert_eq!(distance(6), 1);
+    assert_eq!(distance(11), 2);
+    assert_eq!(distance(20), 4);
+    assert_eq!(distance(21), 4);
+}
+
+#[test]
+fn test_flood_fill_huge_board() {
+    // A single bomb in the corner leaves one big empty area to fill
+    let map = Map::with_bombs(500, 500, &[0]);
+    let opened = map.flood_fill(CellIndex(250 * 500 + 250), |_| true);
+    assert_eq!(opened.len(), 500 * 500 - 1);
+}
+
+/// Run with `cargo test --release bench_flood_fill -- --ignored --nocapture`
+#[test]
+#[ignore]
+fn bench_flood_fill() {
+    let mut map = Map::with_seed(500, 500, 0);
+    map.set_bombs_around(500, CellIndex(250 * 500 + 250));
+
+    let runs = 20;
+    let start = std::time::Instant::now();
+    let mut opened = 0;
+    for _ in 0..runs {
+        opened = map.flood_fill(CellIndex(250 * 500 + 250), |_| true).len();
+    }
+    let per_run = start.elapsed() / runs;
+    println!("opened {opened} tiles of a 500x500 board in {per_run:?}");
+    assert!(
+        per_run.as_millis() < 16,
+        "a flood fill takes longer than a frame"
+    );
+}
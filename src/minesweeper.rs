@@ -1,30 +1,56 @@
-use bevy::{prelude::*, utils::HashMap};
-use bevy_mod_picking::PickingEvent;
+use bevy::{prelude::*, utils::HashSet};
+use bevy_mod_picking::{PickingCamera, PickingEvent};
 use heron::prelude::*;
-use rand::{thread_rng, Rng};
+use nalgebra_glm::smoothstep;
+use rand::Rng;
 
 use crate::{
+    effects::{self, ExplosionAssets, Lifetime, Revealing},
     killscreen::{GameOverEvent, KillScreen},
-    map_generator::Map,
-    params::Params,
+    map_actions::TileMaterials,
+    map_generator::{CellIndex, Map},
+    params::{BombTrigger, Generation, Params},
     stages::GameStage,
-    tile::{Tile, TileKind},
+    stats::GameStats,
+    stress::Stress,
+    tile::{Tile, TileIndex, TileKind, TileState},
 };
 
 #[derive(Debug)]
 pub struct BoomEvent {
     pub entity: Entity,
     pub source: Vec3,
+    /// How many explosions led up to this one, 0 for the bomb that started
+    /// it all
+    pub chain: usize,
+}
+
+/// A bomb was set off by shrapnel from another one
+#[derive(Debug)]
+pub struct ChainReactionEvent {
+    /// Number of explosions in the chain so far, including this one
+    pub length: usize,
 }
 
 #[derive(Debug)]
 pub struct ClearTileEvent {
     pub entity: Entity,
-    pub tile: Tile,
 }
 
+/// Every tile [`clear`] revealed in a frame, in the order they were reached.
+/// Sent at most once per frame, and only if something was revealed.
+#[derive(Debug)]
+pub struct TilesRevealedEvent {
+    pub indices: Vec<usize>,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn click_on_tile(
-    tiles: Query<(Entity, &Tile, &Transform)>,
+    params: Res<Params>,
+    mut map: ResMut<Map>,
+    mut stats: ResMut<GameStats>,
+    tile_index: Res<TileIndex>,
+    mut tiles: Query<(Entity, &mut Tile, &Transform, &mut PhysicMaterial)>,
     mut stage: ResMut<State<GameStage>>,
     mut events: EventReader<PickingEvent>,
     mut boom: EventWriter<BoomEvent>,
@@ -34,137 +60,271 @@ pub fn click_on_tile(
 ) {
     for event in events.iter() {
         if let PickingEvent::Clicked(e) = event {
-            if let Some((entity, tile, transform)) = tiles.iter().find(|(tile, ..)| e == tile) {
-                if *stage.current() != GameStage::MapSet {
-                    let _ = stage.set(GameStage::MapSet);
+            if *stage.current() == GameStage::NewGame && map.bombs == 0 {
+                match tiles.get(*e) {
+                    Ok((_, tile, ..)) if tile.state != TileState::Flagged => {
+                        let index = tile.index_in_map;
+                        place_bombs(&params, &mut map, index, &mut tiles);
+                    }
+                    _ => {}
                 }
+            }
 
-                match tile.kind {
-                    TileKind::Boom => {
-                        info!("Boom in aisle {tile:?}");
-                        crate::stages::endgame(&mut commands);
-                        boom.send(BoomEvent {
-                            entity,
-                            source: transform.translation,
-                        });
-                        commands.entity(entity).despawn();
-                        game_over.send(GameOverEvent);
-                        return;
-                    }
-                    TileKind::Danger(_) => clear.send(ClearTileEvent {
-                        entity,
-                        tile: tile.clone(),
-                    }),
-                    TileKind::Fine => {
-                        clear.send(ClearTileEvent {
+            let (tile, transform) = match tiles.get(*e) {
+                Ok((_, tile, transform, _)) if tile.state != TileState::Flagged => {
+                    (tile, transform)
+                }
+                _ => continue,
+            };
+            stats.clicks += 1;
+            if *stage.current() == GameStage::NewGame {
+                let _ = stage.set(GameStage::MapSet);
+            }
+
+            let to_reveal = match (tile.state, tile.kind.number()) {
+                (TileState::Revealed, Some(n)) => {
+                    chord(&map, &tile_index, tile.index_in_map, n, &tiles)
+                }
+                _ => vec![(*e, tile.clone(), transform.translation)],
+            };
+
+            for (entity, tile, position) in to_reveal {
+                if tile.kind == TileKind::Boom {
+                    info!("Boom in aisle {tile:?}");
+                    crate::stages::endgame(&mut commands, &params);
+                    if let Ok((_, mut tile, ..)) = tiles.get_mut(entity) {
+                        detonate(
+                            &mut commands,
+                            &mut map,
+                            &mut boom,
                             entity,
-                            tile: tile.clone(),
-                        });
+                            &mut tile,
+                            position,
+                            0,
+                        );
                     }
-                    TileKind::Defused(_) => {}
+                    game_over.send(GameOverEvent);
+                    return;
                 }
+                clear.send(ClearTileEvent { entity });
             }
         }
     }
 }
 
-pub fn clear(
-    map: Res<Map>,
-    mut events: EventReader<ClearTileEvent>,
-    mut commands: Commands,
-    tiles: Query<(&Tile, Entity)>,
+/// Clicking a revealed number that has as many flags around it as it says
+/// reveals all its other neighbors, which had better not be bombs
+fn chord(
+    map: &Map,
+    tile_index: &TileIndex,
+    index: usize,
+    number: u8,
+    tiles: &Query<(Entity, &mut Tile, &Transform, &mut PhysicMaterial)>,
+) -> Vec<(Entity, Tile, Vec3)> {
+    let hidden_neighbors: Vec<_> = map
+        .neighbors(CellIndex(index))
+        .filter_map(|(_, index, _)| tiles.get(tile_index.entity(index.0)?).ok())
+        .filter(|(_, tile, ..)| tile.state != TileState::Revealed)
+        .collect();
+
+    let flags = hidden_neighbors
+        .iter()
+        .filter(|(_, tile, ..)| tile.state == TileState::Flagged)
+        .count();
+    if flags != number as usize {
+        return vec![];
+    }
+
+    hidden_neighbors
+        .into_iter()
+        .filter(|(_, tile, ..)| tile.state != TileState::Flagged)
+        .map(|(entity, tile, transform, _)| (entity, tile.clone(), transform.translation))
+        .collect()
+}
+
+/// Right-clicking a tile flags it as a bomb, right-clicking it again marks it
+/// with a question mark instead, and a third time takes the mark away.
+///
+/// Flagged tiles can't be revealed and are pinned in place so that they are
+/// safe from being shaken around.
+pub fn flag_tile(
+    mouse: Res<Input<MouseButton>>,
+    materials: Res<TileMaterials>,
+    mut map: ResMut<Map>,
+    mut stats: ResMut<GameStats>,
+    cameras: Query<&PickingCamera>,
+    mut tiles: Query<(&mut Tile, &mut RigidBody, &mut Handle<StandardMaterial>)>,
 ) {
-    let mut events = events.iter();
-    let ClearTileEvent { entity, tile } = if let Some(x) = events.next() {
-        x
-    } else {
+    if !mouse.just_pressed(MouseButton::Right) {
         return;
+    }
+    let entity = match cameras.iter().find_map(|camera| camera.intersect_top()) {
+        Some((entity, _)) => entity,
+        None => return,
     };
-    let remaining = events.count();
-    if remaining > 0 {
-        warn!("gonna ignore {remaining} events");
+    let (mut tile, mut body, mut material) = match tiles.get_mut(entity) {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+    let (was_flagged, next) = match tile.state.next_mark() {
+        Some(next) => (tile.state == TileState::Flagged, next),
+        None => return,
+    };
+    if !tile.set_state(&mut map, next) {
+        return;
     }
 
-    #[derive(Debug)]
-    struct Thingy {
-        pub index: usize,
-        pub entity: Entity,
+    stats.clicks += 1;
+    if next == TileState::Flagged {
+        stats.flags += 1;
+        *body = RigidBody::Static;
+    } else if was_flagged {
+        stats.flags -= 1;
+        *body = RigidBody::Dynamic;
     }
+    *material = materials.for_tile(&tile);
+}
 
-    let mut existing_tiles: HashMap<usize, (Tile, Entity)> = tiles
-        .iter()
-        .map(|(tile, entity)| (tile.index_in_map, (tile.clone(), entity)))
-        .collect();
+/// Bombs are placed lazily so that the first click can never hit one
+fn place_bombs(
+    params: &Params,
+    map: &mut Map,
+    first_click: usize,
+    tiles: &mut Query<(Entity, &mut Tile, &Transform, &mut PhysicMaterial)>,
+) {
+    let first_click = CellIndex(first_click);
+    match params.generation {
+        Generation::Random => map.set_bombs_around(params.bomb_count, first_click),
+        Generation::NoGuess { attempts } => {
+            if !map.set_bombs_without_guessing(params.bomb_count, first_click, attempts) {
+                warn!("no board without guessing found, good luck");
+            }
+        }
+    }
 
-    let me = Thingy {
-        entity: *entity,
-        index: tile.index_in_map,
+    for (_, mut tile, _, mut material) in tiles.iter_mut() {
+        tile.kind = map.map[tile.index_in_map];
+        *material = tile.kind.physic_material();
+    }
+}
+
+/// Tiles are revealed right away as far as the game is concerned, but only
+/// turn over one ring at a time, see `effects::flip_revealed`
+#[allow(clippy::too_many_arguments)]
+pub fn clear(
+    mut map: ResMut<Map>,
+    params: Res<Params>,
+    materials: Res<TileMaterials>,
+    tile_index: Res<TileIndex>,
+    mut events: EventReader<ClearTileEvent>,
+    mut revealed_events: EventWriter<TilesRevealedEvent>,
+    mut commands: Commands,
+    mut tiles: Query<(Entity, &mut Tile, &mut Transform)>,
+) {
+    // Revealed and flagged tiles are left alone, and so are tiles an earlier
+    // event in the same frame already took care of, as happens with chording
+    // or quick clicking
+    let mut revealed = Vec::new();
+    let mut opened = HashSet::default();
+    let is_hidden = |index: CellIndex, opened: &HashSet<CellIndex>| {
+        !opened.contains(&index)
+            && matches!(
+                map.state(index),
+                Some(TileState::Hidden | TileState::Questioned)
+            )
     };
 
-    clear_neighbors(&map, me, &mut commands, &mut existing_tiles);
-
-    fn clear_neighbors(
-        map: &Map,
-        me: Thingy,
-        commands: &mut Commands,
-        existing_tiles: &mut HashMap<usize, (Tile, Entity)>,
-    ) {
-        let neighbors = map.neighbors(me.index);
-        let neighbors_we_care_about: Vec<_> = neighbors
-            .iter()
-            .filter(|(_coords, index, kind)| {
-                existing_tiles.get(index).is_some() && *kind == TileKind::Fine
-            })
-            .collect();
-
-        commands.entity(me.entity).despawn();
-        existing_tiles.remove(&me.index);
-
-        for (_coords, index, _kind) in neighbors_we_care_about {
-            let (_tile, entity) = if let Some(e) = existing_tiles.get(index) {
-                e
-            } else {
-                continue;
-            };
-            clear_neighbors(
-                map,
-                Thingy {
-                    index: *index,
-                    entity: *entity,
-                },
-                commands,
-                existing_tiles,
-            );
+    for ClearTileEvent { entity } in events.iter() {
+        let index = match tile_index.index(*entity) {
+            Some(index) => CellIndex(index),
+            None => continue,
+        };
+        let newly_opened = map.flood_fill_by_distance(index, |index| is_hidden(index, &opened));
+        for (index, distance) in newly_opened {
+            opened.insert(index);
+            revealed.extend(tile_index.entity(index.0).map(|entity| (entity, distance)));
         }
+    }
 
-        let defused_neighbors: Vec<_> = neighbors
-            .iter()
-            .filter(|(_coords, index, kind)| {
-                existing_tiles.get(index).is_some() && matches!(*kind, TileKind::Danger(_))
-            })
-            .collect();
-
-        for (_coords, index, _kind) in defused_neighbors {
-            let (tile, entity) = if let Some(e) = existing_tiles.get(index) {
-                e
-            } else {
-                continue;
-            };
-            commands.entity(*entity).despawn();
+    let mut indices = Vec::with_capacity(revealed.len());
+    for (entity, distance) in revealed {
+        let (entity, mut tile, mut transform) = match tiles.get_mut(entity) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+        let n = match tile.kind.number() {
+            Some(n) => n,
+            None => continue,
+        };
+        if !tile.set_state(&mut map, TileState::Revealed) {
+            continue;
         }
+
+        indices.push(tile.index_in_map);
+        // Revealed tiles go back to where they belong and stay there, empty
+        // ones as a flat piece of floor once they have turned over
+        *transform = Transform::from_translation(tile.original_position);
+        commands
+            .entity(entity)
+            .insert(RigidBody::Sensor)
+            .insert(Revealing::new(
+                distance as f32 * params.reveal_ripple,
+                materials.for_tile(&tile),
+                n == 0,
+            ))
+            .remove::<Velocity>();
+    }
+
+    if !indices.is_empty() {
+        revealed_events.send(TilesRevealedEvent { indices });
+    }
+}
+
+/// Every bomb goes off through here, which marks it as exploded in the same
+/// frame it is despawned and [`go_nuclear`] takes over
+///
+/// Returns `false` without doing anything if the bomb already went off, which
+/// happens when several systems catch it before the despawn lands
+pub fn detonate(
+    commands: &mut Commands,
+    map: &mut Map,
+    boom: &mut EventWriter<BoomEvent>,
+    entity: Entity,
+    tile: &mut Tile,
+    source: Vec3,
+    chain: usize,
+) -> bool {
+    if !tile.set_state(map, TileState::Exploded) {
+        return false;
     }
+    boom.send(BoomEvent {
+        entity,
+        source,
+        chain,
+    });
+    commands.entity(entity).despawn();
+    true
 }
 
 pub fn go_nuclear(
     mut app_state: ResMut<State<GameStage>>,
+    params: Res<Params>,
+    assets: Res<ExplosionAssets>,
+    mut map: ResMut<Map>,
     mut events: EventReader<BoomEvent>,
+    shrapnel: Query<(), With<Shrapnel>>,
     mut commands: Commands,
 ) {
-    let mut rng = thread_rng();
-    for BoomEvent { entity: _, source } in events.iter() {
+    let rng = map.rng();
+    let mut alive = shrapnel.iter().count();
+    for BoomEvent { source, chain, .. } in events.iter() {
         let _ = app_state.set(GameStage::KillScreen);
+        effects::spawn_explosion(&mut commands, &assets, rng, *source);
 
-        for i in 0..20 {
+        // Big chain reactions would otherwise bring the physics to a crawl
+        let pieces = 20.min(params.max_shrapnel.saturating_sub(alive));
+        alive += pieces;
+        for i in 0..pieces {
             let direction = Vec3::new(
                 (rng.gen_range::<i32, _>(0..2000) - 1000) as f32,
                 (rng.gen_range::<i32, _>(0..2000) - 1000) as f32,
@@ -172,6 +332,8 @@ pub fn go_nuclear(
             );
             commands
                 .spawn_bundle(PbrBundle {
+                    mesh: assets.shrapnel_mesh.clone(),
+                    material: assets.shrapnel_material.clone(),
                     transform: Transform::from_translation(*source),
                     ..Default::default()
                 })
@@ -184,40 +346,212 @@ pub fn go_nuclear(
                 })
                 .insert(Velocity::from_linear(*source + direction))
                 .insert(RotationConstraints::lock())
-                .insert(Shrapnel)
+                .insert(Shrapnel { chain: *chain })
+                .insert(Lifetime::from_seconds(params.shrapnel_lifetime))
                 .insert(Name::new(format!("Boom {i}")));
         }
     }
 }
 
+/// Explosions push every tile around them away, the closer the harder
+pub fn blast_wave(
+    params: Res<Params>,
+    mut events: EventReader<BoomEvent>,
+    mut tiles: Query<(&Tile, &Transform, &PhysicMaterial, &mut Velocity)>,
+) {
+    for BoomEvent { source, .. } in events.iter() {
+        for (tile, transform, material, mut velocity) in tiles.iter_mut() {
+            // Flags keep their tiles pinned even through this
+            if tile.state == TileState::Flagged {
+                continue;
+            }
+            let away = transform.translation - *source;
+            let falloff = params.blast_falloff(away.length());
+            if falloff <= 0. {
+                continue;
+            }
+            let push = away.normalize_or_zero() * params.blast_strength * falloff;
+            *velocity = velocity.with_linear(velocity.linear + push / material.density);
+        }
+    }
+}
+
+/// Bombs remember how much they have been shaken, see [`Stress`]
+pub fn stress_bombs(
+    time: Res<Time>,
+    params: Res<Params>,
+    mut tiles: Query<(&Tile, &Velocity, &mut Stress)>,
+) {
+    let model = match params.bomb_trigger {
+        BombTrigger::Stress(model) => model,
+        BombTrigger::Velocity => return,
+    };
+    for (tile, velocity, mut stress) in tiles.iter_mut() {
+        if tile.kind == TileKind::Boom {
+            stress.update(velocity.linear, time.delta_seconds(), &model);
+        }
+    }
+}
+
 pub fn go_nuclear_if_fast(
     params: Res<Params>,
-    tiles: Query<(&Tile, &Velocity, Entity, &Transform)>,
+    mut map: ResMut<Map>,
+    mut tiles: Query<(&mut Tile, &Velocity, &Stress, Entity, &Transform)>,
     mut boom: EventWriter<BoomEvent>,
     mut commands: Commands,
     has_killscreen: Query<(), With<KillScreen>>,
     mut game_over: EventWriter<GameOverEvent>,
 ) {
-    let blow_threshold = params.bomb_velocity_threshold;
     let bombs = tiles
-        .iter()
-        .filter(|(tile, ..)| tile.kind == TileKind::Boom);
+        .iter_mut()
+        .filter(|(tile, ..)| tile.kind == TileKind::Boom && tile.state != TileState::Exploded);
     let fast_bombs = bombs
-        .filter(|(_, velocity, ..)| velocity.linear.distance(Vec3::ZERO) > blow_threshold)
+        .filter(|(_, velocity, stress, ..)| params.bomb_explodes(velocity.linear.length(), stress))
         .collect::<Vec<_>>();
 
     if !fast_bombs.is_empty() && has_killscreen.iter().next().is_none() {
         game_over.send(GameOverEvent);
     }
 
-    for (_, _, entity, source) in fast_bombs {
-        boom.send(BoomEvent {
+    for (mut tile, _, _, entity, source) in fast_bombs {
+        let source = source.translation;
+        detonate(
+            &mut commands,
+            &mut map,
+            &mut boom,
             entity,
-            source: source.translation,
-        });
-        commands.entity(entity).despawn();
+            &mut tile,
+            source,
+            0,
+        );
+    }
+}
+
+/// Bombs swell up as they get close to exploding, so careful players can
+/// back off in time
+pub fn show_bomb_danger(
+    params: Res<Params>,
+    mut tiles: Query<(&Tile, &Velocity, &Stress, &mut Transform)>,
+) {
+    for (tile, velocity, stress, mut transform) in tiles.iter_mut() {
+        if tile.kind != TileKind::Boom {
+            continue;
+        }
+        let danger = params.bomb_danger(velocity.linear.length(), stress);
+        transform.scale = Vec3::splat(1. + 0.4 * smoothstep(0.5, 1., danger));
+    }
+}
+
+/// Bombs hit by shrapnel moving faster than `Params::chain_reaction_speed`
+/// go off as well
+#[allow(clippy::too_many_arguments)]
+pub fn chain_reaction(
+    params: Res<Params>,
+    mut map: ResMut<Map>,
+    mut collisions: EventReader<CollisionEvent>,
+    shrapnel: Query<(&Shrapnel, &Velocity)>,
+    mut tiles: Query<(&mut Tile, &Transform)>,
+    mut boom: EventWriter<BoomEvent>,
+    mut chain_reactions: EventWriter<ChainReactionEvent>,
+    mut commands: Commands,
+) {
+    let threshold = match params.chain_reaction_speed {
+        Some(threshold) => threshold,
+        None => return,
+    };
+    for collision in collisions.iter().filter(|c| c.is_started()) {
+        let (a, b) = collision.rigid_body_entities();
+        for (piece, bomb) in [(a, b), (b, a)] {
+            let (chain, speed) = match shrapnel.get(piece) {
+                Ok((Shrapnel { chain }, velocity)) => (*chain, velocity.linear.length()),
+                Err(_) => continue,
+            };
+            let (mut tile, transform) = match tiles.get_mut(bomb) {
+                Ok((tile, transform))
+                    if tile.kind == TileKind::Boom && tile.state != TileState::Exploded =>
+                {
+                    (tile, transform)
+                }
+                _ => continue,
+            };
+            if speed < threshold {
+                continue;
+            }
+
+            let chain = chain + 1;
+            let length = chain + 1;
+            let source = transform.translation;
+            // A bomb can be hit by several pieces in the same frame, or be
+            // caught by `go_nuclear_if_fast` first, but it only explodes once
+            if detonate(
+                &mut commands,
+                &mut map,
+                &mut boom,
+                bomb,
+                &mut tile,
+                source,
+                chain,
+            ) {
+                info!("chain reaction of {length} explosions");
+                chain_reactions.send(ChainReactionEvent { length });
+            }
+        }
     }
 }
 
 #[derive(Component, Debug)]
-pub struct Shrapnel;
+pub struct Shrapnel {
+    /// [`BoomEvent::chain`] of the explosion this came from
+    pub chain: usize,
+}
+
+#[cfg(test)]
+fn detonate_every_bomb(
+    mut map: ResMut<Map>,
+    mut tiles: Query<(Entity, &mut Tile)>,
+    mut boom: EventWriter<BoomEvent>,
+    mut commands: Commands,
+) {
+    for (entity, mut tile) in tiles.iter_mut() {
+        if tile.kind == TileKind::Boom {
+            detonate(
+                &mut commands,
+                &mut map,
+                &mut boom,
+                entity,
+                &mut tile,
+                Vec3::ZERO,
+                0,
+            );
+        }
+    }
+}
+
+#[test]
+fn test_detonate_once() {
+    use bevy::app::Events;
+
+    // Two systems catch the same bomb in one frame, before the despawn from
+    // the first one lands
+    let mut world = World::new();
+    world.insert_resource(Map::with_bombs(1, 2, &[0]));
+    world.insert_resource(Events::<BoomEvent>::default());
+    let bomb = world
+        .spawn()
+        .insert(Tile {
+            kind: TileKind::Boom,
+            index_in_map: 0,
+            ..Default::default()
+        })
+        .id();
+    SystemStage::single_threaded()
+        .with_system(detonate_every_bomb)
+        .with_system(detonate_every_bomb)
+        .run(&mut world);
+
+    let events = world.get_resource::<Events<BoomEvent>>().unwrap();
+    assert_eq!(events.get_reader().iter(events).count(), 1);
+    assert!(world.get_entity(bomb).is_none());
+    let map = world.get_resource::<Map>().unwrap();
+    assert_eq!(map.state(CellIndex(0)), Some(TileState::Exploded));
+}
@@ -0,0 +1,65 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+const SIZE: usize = 32;
+const SCALE: usize = 4;
+
+const BACKGROUND: [u8; 3] = [192, 192, 192];
+
+/// The colors numbers have had since the olden days
+const COLORS: [[u8; 3]; 8] = [
+    [0, 0, 255],
+    [0, 128, 0],
+    [255, 0, 0],
+    [0, 0, 128],
+    [128, 0, 0],
+    [0, 128, 128],
+    [0, 0, 0],
+    [128, 128, 128],
+];
+
+/// 3x5 pixel glyphs, top row first
+const GLYPHS: [[&str; 5]; 8] = [
+    [".#.", "##.", ".#.", ".#.", "###"],
+    ["##.", "..#", ".#.", "#..", "###"],
+    ["##.", "..#", ".#.", "..#", "##."],
+    ["#.#", "#.#", "###", "..#", "..#"],
+    ["###", "#..", "##.", "..#", "##."],
+    [".##", "#..", "###", "#.#", "###"],
+    ["###", "..#", ".#.", ".#.", ".#."],
+    ["###", "#.#", "###", "#.#", "###"],
+];
+
+/// Texture for a revealed tile with `number` bombs around it (1 to 8)
+pub fn texture(number: u8) -> Image {
+    let glyph = GLYPHS[number as usize - 1];
+    let color = COLORS[number as usize - 1];
+    let (glyph_width, glyph_height) = (3 * SCALE, 5 * SCALE);
+    let (left, top) = ((SIZE - glyph_width) / 2, (SIZE - glyph_height) / 2);
+
+    let mut data = Vec::with_capacity(SIZE * SIZE * 4);
+    for y in 0..SIZE {
+        // Cube faces have their UV origin at the bottom, so we go bottom up
+        let y = SIZE - 1 - y;
+        for x in 0..SIZE {
+            let is_glyph = (left..left + glyph_width).contains(&x)
+                && (top..top + glyph_height).contains(&y)
+                && glyph[(y - top) / SCALE].as_bytes()[(x - left) / SCALE] == b'#';
+            let [r, g, b] = if is_glyph { color } else { BACKGROUND };
+            data.extend_from_slice(&[r, g, b, 255]);
+        }
+    }
+
+    Image::new(
+        Extent3d {
+            width: SIZE as u32,
+            height: SIZE as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
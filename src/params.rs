@@ -1,16 +1,156 @@
+use bevy::math::Vec2;
+
+use crate::stress::{Stress, StressModel};
+
+/// How bombs are placed once the player clicks the first tile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Generation {
+    /// Anywhere, which can leave the player with nothing but a guess
+    Random,
+    /// Try this many layouts for one that needs no guessing, falling back to
+    /// the last random one if none does
+    NoGuess { attempts: usize },
+}
+
+/// What happens to tiles that somehow get past the walls around the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutOfBounds {
+    /// Put them back where they started
+    Respawn,
+    /// Gone for good, the board can be won without revealing them. No preset
+    /// uses this, it has to be set in code
+    Lost,
+    /// Bombs blow up, everything else is put back
+    Detonate,
+}
+
+/// What makes a bomb go off
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BombTrigger {
+    /// Moving faster than `bomb_velocity_threshold`, even for a moment
+    Velocity,
+    /// Taking more abuse over time than it can stand
+    Stress(StressModel),
+}
+
+/// Largest custom board in rows, columns and bombs, two and a half times an
+/// expert board. Any bigger and the physics and no-guess generation drag.
+const MAX_CUSTOM_BOARD: (usize, usize, usize) = (30, 40, 300);
+
+/// Board size and how touchy the tiles are, picked on the start screen and
+/// kept for every game after that
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difficulty {
+    Beginner,
+    Intermediate,
+    Expert,
+    /// Any board, with the physics of [`Difficulty::Intermediate`]
+    Custom {
+        rows: usize,
+        columns: usize,
+        bombs: usize,
+    },
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Self::Intermediate
+    }
+}
+
+impl Difficulty {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Beginner => "Beginner",
+            Difficulty::Intermediate => "Intermediate",
+            Difficulty::Expert => "Expert",
+            Difficulty::Custom { .. } => "Custom",
+        }
+    }
+
+    /// Rows, columns and bombs
+    pub fn board(&self) -> (usize, usize, usize) {
+        match *self {
+            Difficulty::Beginner => (9, 9, 10),
+            Difficulty::Intermediate => (16, 16, 40),
+            Difficulty::Expert => (16, 30, 99),
+            Difficulty::Custom {
+                rows,
+                columns,
+                bombs,
+            } => (rows, columns, bombs),
+        }
+    }
+
+    /// A custom board that many rows, columns and bombs away from this one,
+    /// with at least one of each and no more than [`MAX_CUSTOM_BOARD`]
+    pub fn resized(&self, rows: isize, columns: isize, bombs: isize) -> Self {
+        let (old_rows, old_columns, old_bombs) = self.board();
+        let (max_rows, max_columns, max_bombs) = MAX_CUSTOM_BOARD;
+        let add =
+            |n: usize, by: isize, max: usize| (n as isize + by).clamp(1, max as isize) as usize;
+        Difficulty::Custom {
+            rows: add(old_rows, rows, max_rows),
+            columns: add(old_columns, columns, max_columns),
+            bombs: add(old_bombs, bombs, max_bombs),
+        }
+    }
+
+    /// [`Params::regular`] for this board, with bombs that are harder to set
+    /// off on easier ones
+    pub fn params(&self) -> Params {
+        let (map_rows, map_columns, bomb_count) = self.board();
+        let (mouse_move_speed, bomb_velocity_threshold, go_home_factor) = match self {
+            Difficulty::Beginner => (600., 400., 200. * 60.),
+            Difficulty::Intermediate | Difficulty::Custom { .. } => (800., 300., 150. * 60.),
+            Difficulty::Expert => (1000., 250., 120. * 60.),
+        };
+        Params {
+            map_rows,
+            map_columns,
+            bomb_count,
+            mouse_move_speed,
+            bomb_velocity_threshold,
+            go_home_factor,
+            ..Params::regular()
+        }
+    }
+}
+
 pub struct Params {
     pub block_size: f32,
     pub block_offset: f32,
     pub map_rows: usize,
     pub map_columns: usize,
     pub bomb_count: usize,
-    pub hint: bool,
+    /// Fixed seed for board generation, random for every game if `None`
+    pub seed: Option<u64>,
+    pub generation: Generation,
     pub go_home_factor: f32,
     go_home_influence: (f32, f32),
     pub keyboard_move_speed: f32,
     pub mouse_move_speed: f32,
     pub mouse_influence: (f32, f32),
     pub bomb_velocity_threshold: f32,
+    pub bomb_trigger: BombTrigger,
+    /// Shrapnel at least this fast sets off the bombs it hits, `None` to keep
+    /// explosions from spreading
+    pub chain_reaction_speed: Option<f32>,
+    /// How far from an explosion tiles still get pushed away
+    pub blast_radius: f32,
+    /// Push given to a tile right next to an explosion, heavier tiles move less
+    pub blast_strength: f32,
+    /// Seconds before shrapnel fades away
+    pub shrapnel_lifetime: f32,
+    /// No more shrapnel is spawned while this many pieces are flying around
+    pub max_shrapnel: usize,
+    /// Seconds between one ring of revealed tiles turning over and the next,
+    /// counting out from the tile that was clicked
+    pub reveal_ripple: f32,
+    /// Room between the board and the walls around it, read when the walls
+    /// are built for the chosen `Difficulty`
+    pub arena_margin: f32,
+    pub out_of_bounds: OutOfBounds,
 }
 
 impl Params {
@@ -21,13 +161,23 @@ impl Params {
             map_rows: 20,
             map_columns: 20,
             bomb_count: 80,
-            hint: false,
+            seed: None,
+            generation: Generation::Random,
             go_home_factor: 150. * 60.,
             go_home_influence: (0., 100.),
             keyboard_move_speed: 0.5,
             mouse_move_speed: 800.,
             mouse_influence: (50., 200.),
             bomb_velocity_threshold: 300.,
+            bomb_trigger: BombTrigger::Velocity,
+            chain_reaction_speed: Some(400.),
+            blast_radius: 150.,
+            blast_strength: 1500.,
+            shrapnel_lifetime: 3.,
+            max_shrapnel: 200,
+            reveal_ripple: 0.04,
+            arena_margin: 100.,
+            out_of_bounds: OutOfBounds::Respawn,
         }
     }
 
@@ -38,20 +188,71 @@ impl Params {
             map_rows: 20,
             map_columns: 20,
             bomb_count: 80,
-            hint: false,
+            seed: None,
+            generation: Generation::Random,
             go_home_factor: 30.,
             go_home_influence: (100., 600.),
             keyboard_move_speed: 0.5,
             mouse_move_speed: 400.,
             mouse_influence: (100., 600.),
             bomb_velocity_threshold: 100.,
+            bomb_trigger: BombTrigger::Velocity,
+            chain_reaction_speed: Some(150.),
+            blast_radius: 250.,
+            blast_strength: 3000.,
+            shrapnel_lifetime: 5.,
+            max_shrapnel: 400,
+            reveal_ripple: 0.02,
+            arena_margin: 100.,
+            out_of_bounds: OutOfBounds::Detonate,
         }
     }
 
+    /// Like [`Params::regular`], but bombs put up with a quick jolt and blow
+    /// up from being knocked around for too long instead
+    pub fn stressful() -> Self {
+        Self {
+            bomb_trigger: BombTrigger::Stress(StressModel {
+                limit: 150.,
+                velocity_factor: 1.,
+                acceleration_factor: 0.05,
+                recovery: 60.,
+            }),
+            ..Self::regular()
+        }
+    }
+
+    /// Half the width and height of the space inside the walls
+    pub fn arena_half_size(&self) -> Vec2 {
+        let board = Vec2::new(self.map_columns as f32, self.map_rows as f32) * self.block_offset;
+        board / 2. + Vec2::splat(self.arena_margin)
+    }
+
     pub fn go_home_influence(&self, distance: f32) -> f32 {
         nalgebra_glm::smoothstep(self.go_home_influence.0, self.go_home_influence.1, distance)
     }
 
+    /// How close a bomb moving at `speed` is to exploding, from 0 to 1
+    pub fn bomb_danger(&self, speed: f32, stress: &Stress) -> f32 {
+        let danger = match self.bomb_trigger {
+            BombTrigger::Velocity => speed / self.bomb_velocity_threshold,
+            BombTrigger::Stress(model) => stress.value / model.limit,
+        };
+        danger.clamp(0., 1.)
+    }
+
+    pub fn bomb_explodes(&self, speed: f32, stress: &Stress) -> bool {
+        match self.bomb_trigger {
+            BombTrigger::Velocity => speed > self.bomb_velocity_threshold,
+            BombTrigger::Stress(model) => stress.is_critical(&model),
+        }
+    }
+
+    /// How much of the blast reaches a tile at `distance` from an explosion
+    pub fn blast_falloff(&self, distance: f32) -> f32 {
+        1. - nalgebra_glm::smoothstep(0., self.blast_radius, distance)
+    }
+
     pub fn mouse_influence(&self, distance_from_mouse_pointer: f32) -> f32 {
         1. - nalgebra_glm::smoothstep(
             self.mouse_influence.0,
@@ -60,3 +261,14 @@ impl Params {
         )
     }
 }
+
+#[test]
+fn test_custom_board_limits() {
+    // Custom boards never shrink below a single tile and bomb
+    let tiny = Difficulty::Beginner.resized(-20, -20, -20);
+    assert_eq!(tiny.board(), (1, 1, 1));
+
+    // Nor grow past the biggest one that is still quick to play on
+    let huge = Difficulty::Expert.resized(1000, 1000, 10_000);
+    assert_eq!(huge.board(), MAX_CUSTOM_BOARD);
+}
@@ -0,0 +1,481 @@
+//! Figures out which hidden tiles are certainly safe or certainly bombs, using
+//! only what the player can see: the numbers on revealed tiles and the flags.
+//!
+//! Flags are trusted, i.e. a flagged tile counts as a bomb. A wrong flag can
+//! thus lead to wrong deductions, just like it would for a human player.
+
+use bevy::utils::{HashMap, HashSet};
+
+use crate::map_generator::{CellIndex, Coord, Map};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Safe,
+    Bomb,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    /// The number at `at` is already satisfied by known bombs
+    AllBombsFound { at: usize, number: u8 },
+    /// The number at `at` needs every one of its unknown neighbors to be a bomb
+    AllUnknownAreBombs { at: usize, number: u8 },
+    /// The unknown neighbors of `inner` are all neighbors of `outer` as well,
+    /// so the difference between the two numbers has to be in the remainder
+    Subset { inner: usize, outer: usize },
+    /// Only `remaining` bombs are left for all the unknown tiles of the map
+    BombCount { remaining: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deduction {
+    pub index: usize,
+    pub verdict: Verdict,
+    pub reason: Reason,
+}
+
+impl Deduction {
+    pub fn explain(&self, map: &Map) -> String {
+        let coords = |index: usize| match map.index_to_coord(CellIndex(index)) {
+            Some(Coord { row, col }) => format!("({row}, {col})"),
+            None => format!("#{index}"),
+        };
+        let tile = coords(self.index);
+        let verdict = match self.verdict {
+            Verdict::Safe => "safe",
+            Verdict::Bomb => "a bomb",
+        };
+
+        match self.reason {
+            Reason::AllBombsFound { at, number } => format!(
+                "{tile} is {verdict}: the {number} at {} already has all its bombs",
+                coords(at)
+            ),
+            Reason::AllUnknownAreBombs { at, number } => format!(
+                "{tile} is {verdict}: the {number} at {} has no other tiles left for its bombs",
+                coords(at)
+            ),
+            Reason::Subset { inner, outer } => format!(
+                "{tile} is {verdict}: {} shares all its unknown neighbors with {}, \
+                the rest of which must hold the difference",
+                coords(inner),
+                coords(outer)
+            ),
+            Reason::BombCount { remaining } => format!(
+                "{tile} is {verdict}: {remaining} bombs remain for the tiles no one has touched yet"
+            ),
+        }
+    }
+}
+
+/// What a single revealed number tells us about its unknown neighbors
+#[derive(Debug)]
+struct Constraint {
+    at: usize,
+    number: u8,
+    unknown: Vec<usize>,
+    bombs: usize,
+}
+
+/// What the solver knows about each tile. Looked up for every neighbor of
+/// every number, over and over, hence one per tile rather than sets of indices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Knowledge {
+    Unknown,
+    Revealed,
+    Deduced(Verdict),
+}
+
+/// Everything that can be deduced from the `revealed` and `flagged` tiles.
+///
+/// Deductions build on each other, so a tile found to be a bomb here is used
+/// as if it were flagged to find further deductions. Each tile appears once.
+pub fn deduce(map: &Map, revealed: &HashSet<usize>, flagged: &HashSet<usize>) -> Vec<Deduction> {
+    let mut tiles = vec![Knowledge::Unknown; map.map.len()];
+    for index in flagged {
+        tiles[*index] = Knowledge::Deduced(Verdict::Bomb);
+    }
+    for index in revealed {
+        tiles[*index] = Knowledge::Revealed;
+    }
+
+    let mut numbers: Vec<(usize, u8)> = revealed
+        .iter()
+        .filter_map(|index| number_at(map, *index).map(|number| (*index, number)))
+        .collect();
+    numbers.sort_unstable();
+
+    let mut deductions = Vec::new();
+    deduce_more(map, &mut tiles, &mut numbers, &mut deductions);
+    deductions
+}
+
+/// Adds to `deductions` until nothing new can be found. `numbers` are the
+/// revealed numbers worth looking at, the ones with nothing left to say are
+/// dropped along the way so that callers can keep going from here.
+fn deduce_more(
+    map: &Map,
+    tiles: &mut [Knowledge],
+    numbers: &mut Vec<(usize, u8)>,
+    deductions: &mut Vec<Deduction>,
+) {
+    loop {
+        let constraints = constraints(map, numbers, tiles);
+        let found_before = deductions.len();
+
+        for constraint in &constraints {
+            let verdict = if constraint.bombs == 0 {
+                Verdict::Safe
+            } else if constraint.bombs == constraint.unknown.len() {
+                Verdict::Bomb
+            } else {
+                continue;
+            };
+            let reason = match verdict {
+                Verdict::Safe => Reason::AllBombsFound {
+                    at: constraint.at,
+                    number: constraint.number,
+                },
+                Verdict::Bomb => Reason::AllUnknownAreBombs {
+                    at: constraint.at,
+                    number: constraint.number,
+                },
+            };
+            for index in &constraint.unknown {
+                record(deductions, tiles, *index, verdict, &reason);
+            }
+        }
+
+        if deductions.len() == found_before {
+            deduce_from_subsets(&constraints, deductions, tiles);
+        }
+
+        if deductions.len() == found_before {
+            deduce_from_bomb_count(map, deductions, tiles);
+        }
+
+        if deductions.len() == found_before {
+            return;
+        }
+    }
+}
+
+/// Whether the whole map can be cleared from a click on `first_click`
+/// without ever having to guess
+pub fn solvable_from(map: &Map, first_click: usize) -> bool {
+    let mut tiles = vec![Knowledge::Unknown; map.map.len()];
+    let mut revealed = 0;
+    let mut numbers = Vec::new();
+    let mut deductions = Vec::new();
+
+    // Everything found so far stays known, each round only has to work
+    // through what the last one revealed
+    let mut clicks = vec![first_click];
+    while !clicks.is_empty() {
+        for index in clicks.drain(..) {
+            let opened = map.flood_fill(CellIndex(index), |CellIndex(index)| {
+                tiles[index] != Knowledge::Revealed
+            });
+            revealed += opened.len();
+            for CellIndex(index) in opened {
+                tiles[index] = Knowledge::Revealed;
+                numbers.extend(number_at(map, index).map(|number| (index, number)));
+            }
+        }
+
+        let found_before = deductions.len();
+        deduce_more(map, &mut tiles, &mut numbers, &mut deductions);
+        clicks.extend(
+            deductions[found_before..]
+                .iter()
+                .filter(|deduction| deduction.verdict == Verdict::Safe)
+                .map(|deduction| deduction.index),
+        );
+    }
+
+    revealed + map.bombs == map.map.len()
+}
+
+fn number_at(map: &Map, index: usize) -> Option<u8> {
+    map.map.get(index)?.number()
+}
+
+/// Also drops the `numbers` that have no unknown neighbors left, as they
+/// never will again
+fn constraints(map: &Map, numbers: &mut Vec<(usize, u8)>, tiles: &[Knowledge]) -> Vec<Constraint> {
+    let mut constraints = Vec::new();
+    numbers.retain(|&(at, number)| {
+        let mut unknown = Vec::new();
+        let mut known_bombs = 0;
+        for (_, CellIndex(index), _) in map.neighbors(CellIndex(at)) {
+            match tiles[index] {
+                Knowledge::Deduced(Verdict::Bomb) => known_bombs += 1,
+                Knowledge::Unknown => unknown.push(index),
+                Knowledge::Deduced(Verdict::Safe) | Knowledge::Revealed => {}
+            }
+        }
+        if unknown.is_empty() {
+            return false;
+        }
+
+        // A number with more known bombs than it allows means a flag is
+        // wrong; nothing it says can be trusted then.
+        let bombs = match (number as usize).checked_sub(known_bombs) {
+            Some(bombs) if bombs <= unknown.len() => bombs,
+            _ => return true,
+        };
+        unknown.sort_unstable();
+        constraints.push(Constraint {
+            at,
+            number,
+            unknown,
+            bombs,
+        });
+        true
+    });
+    constraints
+}
+
+fn deduce_from_subsets(
+    constraints: &[Constraint],
+    deductions: &mut Vec<Deduction>,
+    tiles: &mut [Knowledge],
+) {
+    // Only constraints sharing an unknown tile can be subsets of each other
+    let mut by_tile: HashMap<usize, Vec<usize>> = HashMap::default();
+    for (id, constraint) in constraints.iter().enumerate() {
+        for index in &constraint.unknown {
+            by_tile.entry(*index).or_default().push(id);
+        }
+    }
+
+    for inner in constraints {
+        let mut candidates: Vec<usize> = inner
+            .unknown
+            .iter()
+            .flat_map(|index| by_tile[index].iter().copied())
+            .collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        for outer in candidates.into_iter().map(|id| &constraints[id]) {
+            if outer.unknown.len() <= inner.unknown.len()
+                || !inner.unknown.iter().all(|i| outer.unknown.contains(i))
+            {
+                continue;
+            }
+            let rest: Vec<usize> = outer
+                .unknown
+                .iter()
+                .filter(|i| !inner.unknown.contains(i))
+                .copied()
+                .collect();
+            let bombs_in_rest = match outer.bombs.checked_sub(inner.bombs) {
+                Some(x) => x,
+                None => continue,
+            };
+
+            let verdict = if bombs_in_rest == 0 {
+                Verdict::Safe
+            } else if bombs_in_rest == rest.len() {
+                Verdict::Bomb
+            } else {
+                continue;
+            };
+            let reason = Reason::Subset {
+                inner: inner.at,
+                outer: outer.at,
+            };
+            for index in rest {
+                record(deductions, tiles, index, verdict, &reason);
+            }
+        }
+    }
+}
+
+fn deduce_from_bomb_count(map: &Map, deductions: &mut Vec<Deduction>, tiles: &mut [Knowledge]) {
+    let known_bombs = tiles
+        .iter()
+        .filter(|tile| **tile == Knowledge::Deduced(Verdict::Bomb))
+        .count();
+    let remaining = match map.bombs.checked_sub(known_bombs) {
+        Some(x) => x,
+        None => return,
+    };
+    let unknown = tiles
+        .iter()
+        .filter(|tile| **tile == Knowledge::Unknown)
+        .count();
+
+    let verdict = if remaining == 0 {
+        Verdict::Safe
+    } else if remaining == unknown {
+        Verdict::Bomb
+    } else {
+        return;
+    };
+    let reason = Reason::BombCount { remaining };
+    for index in 0..tiles.len() {
+        record(deductions, tiles, index, verdict, &reason);
+    }
+}
+
+/// Only tiles that aren't known yet get a deduction
+fn record(
+    deductions: &mut Vec<Deduction>,
+    tiles: &mut [Knowledge],
+    index: usize,
+    verdict: Verdict,
+    reason: &Reason,
+) {
+    if tiles[index] != Knowledge::Unknown {
+        return;
+    }
+    tiles[index] = Knowledge::Deduced(verdict);
+    deductions.push(Deduction {
+        index,
+        verdict,
+        reason: reason.clone(),
+    });
+}
+
+#[cfg(test)]
+fn verdicts(deductions: &[Deduction]) -> Vec<(usize, Verdict)> {
+    let mut verdicts: Vec<_> = deductions.iter().map(|d| (d.index, d.verdict)).collect();
+    verdicts.sort_unstable_by_key(|(index, _)| *index);
+    verdicts
+}
+
+#[test]
+fn test_all_bombs_found() {
+    // . 1 .
+    // . * .
+    // . . *
+    let map = Map::with_bombs(3, 3, &[4, 8]);
+    let revealed = [1].into_iter().collect();
+    let flagged = [4].into_iter().collect();
+
+    let deductions = deduce(&map, &revealed, &flagged);
+    assert_eq!(
+        verdicts(&deductions),
+        vec![
+            (0, Verdict::Safe),
+            (2, Verdict::Safe),
+            (3, Verdict::Safe),
+            (5, Verdict::Safe)
+        ]
+    );
+    assert!(matches!(
+        deductions[0].reason,
+        Reason::AllBombsFound { at: 1, number: 1 }
+    ));
+}
+
+#[test]
+fn test_all_unknown_are_bombs() {
+    // A corner 1 with only one hidden neighbor left
+    let map = Map::with_bombs(3, 3, &[4]);
+    let revealed = [0, 1, 3].into_iter().collect();
+
+    let deductions = deduce(&map, &revealed, &HashSet::default());
+    assert_eq!(deductions[0].index, 4);
+    assert_eq!(deductions[0].verdict, Verdict::Bomb);
+    assert!(matches!(
+        deductions[0].reason,
+        Reason::AllUnknownAreBombs { at: 0, .. }
+    ));
+}
+
+#[test]
+fn test_subset() {
+    // The classic 1-1 from a wall:
+    //
+    //   1 1 ?
+    //   ? ? ?
+    //   ? ? ?
+    //
+    // The first 1 sees two unknown tiles, the second 1 sees those two and two
+    // more, so these two have to be safe.
+    let map = Map::with_bombs(3, 3, &[3]);
+    let revealed = [0, 1].into_iter().collect();
+
+    let deductions = deduce(&map, &revealed, &HashSet::default());
+    assert_eq!(
+        verdicts(&deductions),
+        vec![(2, Verdict::Safe), (5, Verdict::Safe)]
+    );
+    assert_eq!(deductions[0].reason, Reason::Subset { inner: 0, outer: 1 });
+}
+
+#[test]
+fn test_no_deduction_for_a_guess() {
+    // 1 1
+    // ? ?
+    let map = Map::with_bombs(2, 2, &[2]);
+    let revealed = [0, 1].into_iter().collect();
+
+    assert!(deduce(&map, &revealed, &HashSet::default()).is_empty());
+}
+
+#[test]
+fn test_deductions_are_correct() {
+    for seed in 0..20 {
+        let mut map = Map::with_seed(10, 10, seed);
+        map.set_bombs_around(15, CellIndex(55));
+        let revealed: HashSet<usize> = map
+            .neighbors(CellIndex(55))
+            .map(|(_, index, _)| index.0)
+            .chain([55])
+            .collect();
+
+        for deduction in deduce(&map, &revealed, &HashSet::default()) {
+            let is_bomb = map.map[deduction.index] == crate::tile::TileKind::Boom;
+            assert_eq!(
+                is_bomb,
+                deduction.verdict == Verdict::Bomb,
+                "{}",
+                deduction.explain(&map)
+            );
+        }
+    }
+}
+
+#[test]
+fn test_bomb_count() {
+    // 1 1
+    // ? ?
+    //
+    // Is a guess, unless we know there are no bombs left for the right tile
+    // after flagging the left one.
+    let map = Map::with_bombs(2, 2, &[2]);
+    let revealed = [0, 1].into_iter().collect();
+    let flagged = [2].into_iter().collect();
+
+    let deductions = deduce(&map, &revealed, &flagged);
+    assert_eq!(verdicts(&deductions), vec![(3, Verdict::Safe)]);
+}
+
+#[test]
+fn test_solvable_from() {
+    // * 1 .
+    // 1 1 .
+    // . . .
+    let map = Map::with_bombs(3, 3, &[0]);
+    assert!(solvable_from(&map, 8));
+
+    // 1 1
+    // * .
+    let map = Map::with_bombs(2, 2, &[2]);
+    assert!(!solvable_from(&map, 0));
+}
+
+#[test]
+fn test_solvable_rectangular() {
+    // Opening the whole expert board from a corner
+    let map = Map::with_bombs(16, 30, &[]);
+    assert!(solvable_from(&map, 0));
+
+    // A single bomb tucked in the far corner can be worked out too
+    let map = Map::with_bombs(16, 30, &[479]);
+    assert!(solvable_from(&map, 0));
+}
@@ -10,17 +10,26 @@ pub enum GameStage {
     WinScreen,
 }
 
-pub fn trigger_endgame(keys: Res<Input<KeyCode>>, mut commands: Commands) {
+pub fn trigger_endgame(keys: Res<Input<KeyCode>>, params: Res<Params>, mut commands: Commands) {
     if !keys.just_pressed(KeyCode::Q) {
         return;
     }
 
-    endgame(&mut commands);
+    endgame(&mut commands, &params);
 }
 
-pub fn endgame(commands: &mut Commands) {
+pub fn endgame(commands: &mut Commands, params: &Params) {
     commands.insert_resource(GameStage::KillScreen);
-    commands.insert_resource(Params::chaos());
+    commands.insert_resource(Params {
+        seed: params.seed,
+        generation: params.generation,
+        bomb_trigger: params.bomb_trigger,
+        // Keeps the walls and the HUD in line with the board being played
+        map_rows: params.map_rows,
+        map_columns: params.map_columns,
+        bomb_count: params.bomb_count,
+        ..Params::chaos()
+    });
 }
 
 pub fn trigger_reset(keys: Res<Input<KeyCode>>, mut app_state: ResMut<State<GameStage>>) {
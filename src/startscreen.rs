@@ -1,12 +1,13 @@
-use bevy::prelude::*;
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
 
-use crate::params::Params;
+use crate::{params::Difficulty, stages::GameStage};
 
 #[derive(Debug, Component)]
 pub struct StartScreen;
 
-pub fn draw(asset_server: Res<AssetServer>, params: Res<Params>, mut commands: Commands) {
-    let count = params.bomb_count;
+pub fn draw(asset_server: Res<AssetServer>, difficulty: Res<Difficulty>, mut commands: Commands) {
+    let name = difficulty.name();
+    let (rows, columns, count) = difficulty.board();
     let font = asset_server.load("fonts/FiraSans-Bold.ttf");
 
     commands
@@ -35,13 +36,23 @@ pub fn draw(asset_server: Res<AssetServer>, params: Res<Params>, mut commands: C
                         },
                     },
                     TextSection {
-                        value: format!("There are {count} bombs\n"),
+                        value: format!("{name}: {rows}x{columns}, there are {count} bombs\n"),
                         style: TextStyle {
                             font: font.clone(),
                             font_size: 24.0,
                             color: Color::WHITE,
                         },
                     },
+                    TextSection {
+                        value: "1 Beginner, 2 Intermediate, 3 Expert\n\
+                            Arrows and +/- for a custom board\n"
+                            .to_string(),
+                        style: TextStyle {
+                            font: font.clone(),
+                            font_size: 18.0,
+                            color: Color::GRAY,
+                        },
+                    },
                 ],
                 alignment: TextAlignment {
                     horizontal: HorizontalAlign::Left,
@@ -89,3 +100,55 @@ pub fn draw(asset_server: Res<AssetServer>, params: Res<Params>, mut commands: C
 pub fn hide(start_screen: Query<(Entity,), With<StartScreen>>, mut commands: Commands) {
     start_screen.for_each(|(e,)| commands.entity(e).despawn());
 }
+
+/// Number keys pick a preset, arrows and +/- turn whatever is picked into a
+/// custom board with more or fewer rows, columns and bombs
+pub fn choose_difficulty(
+    keys: Res<Input<KeyCode>>,
+    stage: Res<State<GameStage>>,
+    mut difficulty: ResMut<Difficulty>,
+) {
+    // The board can only change before anyone has clicked on it
+    if *stage.current() != GameStage::NewGame {
+        return;
+    }
+
+    let chosen = if keys.just_pressed(KeyCode::Key1) {
+        Difficulty::Beginner
+    } else if keys.just_pressed(KeyCode::Key2) {
+        Difficulty::Intermediate
+    } else if keys.just_pressed(KeyCode::Key3) {
+        Difficulty::Expert
+    } else if keys.just_pressed(KeyCode::Up) {
+        difficulty.resized(1, 0, 0)
+    } else if keys.just_pressed(KeyCode::Down) {
+        difficulty.resized(-1, 0, 0)
+    } else if keys.just_pressed(KeyCode::Right) {
+        difficulty.resized(0, 1, 0)
+    } else if keys.just_pressed(KeyCode::Left) {
+        difficulty.resized(0, -1, 0)
+    } else if keys.just_pressed(KeyCode::Equals) {
+        difficulty.resized(0, 0, 1)
+    } else if keys.just_pressed(KeyCode::Minus) {
+        difficulty.resized(0, 0, -1)
+    } else {
+        return;
+    };
+
+    // Only a real change rebuilds the board, see `difficulty_changed`
+    if chosen != *difficulty {
+        info!("playing {chosen:?}");
+        *difficulty = chosen;
+    }
+}
+
+/// Runs the systems that build a new game again once another difficulty is
+/// chosen. The first game is built on entering `GameStage::NewGame` as usual,
+/// which is why the difficulty being added doesn't count.
+pub fn difficulty_changed(difficulty: Res<Difficulty>) -> ShouldRun {
+    if difficulty.is_changed() && !difficulty.is_added() {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
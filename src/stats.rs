@@ -0,0 +1,35 @@
+use bevy::prelude::*;
+
+use crate::minesweeper::TilesRevealedEvent;
+
+/// What the player did during the current game, reset with every new map
+#[derive(Debug, Default)]
+pub struct GameStats {
+    /// How often the player asked for a hint that could actually tell them something
+    pub hints: usize,
+    pub flags: usize,
+    /// Left clicks on tiles and flags placed or taken away
+    pub clicks: usize,
+    /// Seconds since startup when the first tile was clicked
+    pub started: Option<f64>,
+    /// Most explosions set off by one another, see `minesweeper::chain_reaction`
+    pub longest_chain: usize,
+    /// Tiles that left the arena, see `params::OutOfBounds::Lost`
+    pub lost: usize,
+    /// Most tiles revealed in a single frame, usually by opening up an empty
+    /// area
+    pub biggest_opening: usize,
+}
+
+impl GameStats {
+    /// What the player has left to find if all their flags are right
+    pub fn bombs_remaining(&self, bomb_count: usize) -> isize {
+        bomb_count as isize - self.flags as isize
+    }
+}
+
+pub fn record_reveals(mut events: EventReader<TilesRevealedEvent>, mut stats: ResMut<GameStats>) {
+    for TilesRevealedEvent { indices } in events.iter() {
+        stats.biggest_opening = stats.biggest_opening.max(indices.len());
+    }
+}
@@ -0,0 +1,99 @@
+use bevy::prelude::*;
+
+/// How bombs take abuse when [`crate::params::BombTrigger::Stress`] is used
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StressModel {
+    /// Bombs explode once their stress goes above this
+    pub limit: f32,
+    /// Stress per second for every unit of speed
+    pub velocity_factor: f32,
+    /// Stress per second for every unit of acceleration, which is also how
+    /// collisions make themselves felt
+    pub acceleration_factor: f32,
+    /// Stress a bomb sheds per second
+    pub recovery: f32,
+}
+
+/// How much abuse a bomb has taken recently
+#[derive(Component, Debug, Default, Clone)]
+pub struct Stress {
+    pub value: f32,
+    last_velocity: Vec3,
+}
+
+impl Stress {
+    /// Account for a bomb moving at `velocity`, `delta` seconds after the
+    /// last update
+    pub fn update(&mut self, velocity: Vec3, delta: f32, model: &StressModel) {
+        if delta <= 0. {
+            return;
+        }
+        let acceleration = (velocity - self.last_velocity).length() / delta;
+        self.last_velocity = velocity;
+
+        let load =
+            model.velocity_factor * velocity.length() + model.acceleration_factor * acceleration;
+        self.value = (self.value + (load - model.recovery) * delta).max(0.);
+    }
+
+    pub fn is_critical(&self, model: &StressModel) -> bool {
+        self.value > model.limit
+    }
+}
+
+#[cfg(test)]
+const MODEL: StressModel = StressModel {
+    limit: 100.,
+    velocity_factor: 1.,
+    acceleration_factor: 0.,
+    recovery: 10.,
+};
+
+#[test]
+fn test_stress_builds_up() {
+    let mut stress = Stress::default();
+    let velocity = Vec3::new(60., 0., 0.);
+    // 50 stress per second
+    for _ in 0..60 {
+        stress.update(velocity, 1. / 30., &MODEL);
+    }
+    assert!((stress.value - 100.).abs() < 0.01);
+    assert!(!stress.is_critical(&MODEL));
+
+    stress.update(velocity, 1. / 30., &MODEL);
+    assert!(stress.is_critical(&MODEL));
+}
+
+#[test]
+fn test_stress_recovers_at_rest() {
+    let mut stress = Stress {
+        value: 50.,
+        ..Default::default()
+    };
+    for _ in 0..10 {
+        stress.update(Vec3::ZERO, 1., &MODEL);
+    }
+    assert_eq!(stress.value, 0.);
+}
+
+#[test]
+fn test_sudden_hits_hurt() {
+    let model = StressModel {
+        velocity_factor: 0.,
+        acceleration_factor: 0.1,
+        ..MODEL
+    };
+    // Both end up going just as fast, but one gets there all at once
+    let mut gentle = Stress::default();
+    let mut hit = Stress::default();
+    for i in 1..=10 {
+        gentle.update(Vec3::new(10. * i as f32, 0., 0.), 0.1, &model);
+        hit.update(
+            Vec3::new(if i == 10 { 100. } else { 0. }, 0., 0.),
+            0.1,
+            &model,
+        );
+    }
+    assert_eq!(gentle.value, 0.);
+    assert!(hit.value > 0.);
+}
@@ -1,7 +1,11 @@
-use bevy::{input::mouse::MouseMotion, prelude::*};
+use bevy::{input::mouse::MouseMotion, prelude::*, utils::HashMap};
 use heron::{PhysicMaterial, Velocity};
+use nalgebra_glm::smoothstep;
 
-use crate::params::Params;
+use crate::{
+    map_generator::{CellIndex, Map},
+    params::Params,
+};
 
 #[derive(Component, Reflect, Default, Debug, Clone)]
 #[reflect(Component)]
@@ -9,6 +13,19 @@ pub struct Tile {
     pub original_position: Vec3,
     pub kind: TileKind,
     pub index_in_map: usize,
+    /// Same as on the map, both change together through [`Tile::set_state`]
+    pub state: TileState,
+}
+
+impl Tile {
+    /// See [`Map::set_state`]
+    pub fn set_state(&mut self, map: &mut Map, next: TileState) -> bool {
+        let changed = map.set_state(CellIndex(self.index_in_map), next);
+        if changed {
+            self.state = next;
+        }
+        changed
+    }
 }
 
 #[derive(Component, Reflect, Debug, Copy, Clone, Eq, PartialEq)]
@@ -18,18 +35,178 @@ pub enum TileKind {
     Boom,
     /// Is a bomb neighbor
     Danger(u8),
-    /// Touched and cleared
-    Defused(u8),
     /// Empty tile
     Fine,
 }
 
+/// What the player did with a tile, as opposed to what it is
+#[derive(Reflect, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TileState {
+    Hidden,
+    /// Marked as a bomb by the player, which pins it in place
+    Flagged,
+    /// Marked as maybe a bomb, which doesn't stop it from being revealed
+    Questioned,
+    Revealed,
+    /// A bomb that went off
+    Exploded,
+    /// Knocked out of the arena, see `params::OutOfBounds::Lost`
+    Lost,
+}
+
+impl Default for TileState {
+    fn default() -> Self {
+        Self::Hidden
+    }
+}
+
+impl TileState {
+    /// Right clicks cycle through hidden, flagged and questioned, flags keep
+    /// tiles from being revealed, and there is no coming back from revealed,
+    /// exploded or lost
+    pub fn can_become(self, next: TileState) -> bool {
+        use TileState::*;
+        matches!(
+            (self, next),
+            (Hidden, Flagged)
+                | (Flagged, Questioned)
+                | (Questioned, Hidden)
+                | (Hidden | Questioned, Revealed)
+                | (Hidden | Flagged | Questioned, Exploded | Lost)
+        )
+    }
+
+    /// Where a right click takes a tile, if anywhere
+    pub fn next_mark(self) -> Option<TileState> {
+        match self {
+            TileState::Hidden => Some(TileState::Flagged),
+            TileState::Flagged => Some(TileState::Questioned),
+            TileState::Questioned => Some(TileState::Hidden),
+            TileState::Revealed | TileState::Exploded | TileState::Lost => None,
+        }
+    }
+}
+
+/// Finds the tile at an index in the map, and the other way around.
+///
+/// Filled in by `map_actions::create_map`, tiles leave it when they are
+/// despawned, see [`forget_despawned`].
+#[derive(Debug, Default)]
+pub struct TileIndex {
+    entities: Vec<Option<Entity>>,
+    indices: HashMap<Entity, usize>,
+}
+
+impl TileIndex {
+    pub fn with_len(len: usize) -> Self {
+        Self {
+            entities: vec![None; len],
+            indices: HashMap::with_capacity(len),
+        }
+    }
+
+    pub fn insert(&mut self, index: usize, entity: Entity) {
+        if index >= self.entities.len() {
+            self.entities.resize(index + 1, None);
+        }
+        if let Some(old) = self.entities[index].replace(entity) {
+            self.indices.remove(&old);
+        }
+        self.indices.insert(entity, index);
+    }
+
+    pub fn entity(&self, index: usize) -> Option<Entity> {
+        self.entities.get(index).copied().flatten()
+    }
+
+    pub fn index(&self, entity: Entity) -> Option<usize> {
+        self.indices.get(&entity).copied()
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<usize> {
+        let index = self.indices.remove(&entity)?;
+        self.entities[index] = None;
+        Some(index)
+    }
+}
+
+#[test]
+fn test_tile_index() {
+    let (a, b, c) = (
+        Entity::from_raw(0),
+        Entity::from_raw(1),
+        Entity::from_raw(2),
+    );
+    let mut tiles = TileIndex::with_len(2);
+    tiles.insert(0, a);
+    tiles.insert(1, b);
+    assert_eq!(tiles.entity(0), Some(a));
+    assert_eq!(tiles.index(b), Some(1));
+    assert_eq!(tiles.entity(2), None);
+
+    // A new tile at the same index takes over, the old one is forgotten
+    tiles.insert(0, c);
+    assert_eq!(tiles.entity(0), Some(c));
+    assert_eq!(tiles.index(c), Some(0));
+    assert_eq!(tiles.index(a), None);
+    assert_eq!(tiles.remove(a), None);
+    assert_eq!(tiles.entity(0), Some(c));
+
+    assert_eq!(tiles.remove(b), Some(1));
+    assert_eq!(tiles.entity(1), None);
+    assert_eq!(tiles.remove(b), None);
+
+    // Indices past the end grow the index
+    tiles.insert(5, a);
+    assert_eq!(tiles.entity(5), Some(a));
+    assert_eq!(tiles.entity(4), None);
+    assert_eq!(tiles.entity(6), None);
+}
+
+pub fn forget_despawned(removed: RemovedComponents<Tile>, mut tile_index: ResMut<TileIndex>) {
+    for entity in removed.iter() {
+        tile_index.remove(entity);
+    }
+}
+
 impl Default for TileKind {
     fn default() -> Self {
         Self::Fine
     }
 }
 
+impl TileKind {
+    /// Bombs around a tile that isn't one itself
+    pub fn number(&self) -> Option<u8> {
+        match self {
+            TileKind::Boom => None,
+            TileKind::Danger(n) => Some(*n),
+            TileKind::Fine => Some(0),
+        }
+    }
+
+    /// Bombs are heavy, and the more bombs are around a tile the heavier it gets
+    pub fn physic_material(&self) -> PhysicMaterial {
+        match self {
+            TileKind::Boom => PhysicMaterial {
+                restitution: 0.2,
+                friction: 0.2,
+                density: 10.,
+            },
+            TileKind::Danger(x) => PhysicMaterial {
+                restitution: 0.9,
+                friction: 0.2,
+                density: 1. + 2. * smoothstep(1., 8., *x as f32),
+            },
+            TileKind::Fine => PhysicMaterial {
+                restitution: 0.9,
+                friction: 0.2,
+                density: 1.,
+            },
+        }
+    }
+}
+
 pub fn input(
     windows: Res<Windows>,
     frame_time: Res<Time>,
@@ -68,7 +245,7 @@ pub fn mouse_input(
     frame_time: Res<Time>,
     params: Res<Params>,
     mut motion: EventReader<MouseMotion>,
-    mut query: Query<(&mut Velocity, &Transform, &PhysicMaterial), With<Tile>>,
+    mut query: Query<(&mut Velocity, &Transform, &PhysicMaterial, &Tile)>,
 ) {
     let mut totaloffset = Vec3::ZERO;
 
@@ -89,7 +266,10 @@ pub fn mouse_input(
 
     totaloffset = totaloffset * params.mouse_move_speed * frame_time.delta_seconds();
 
-    for (mut velocity, transform, physics_material) in query.iter_mut() {
+    for (mut velocity, transform, physics_material, tile) in query.iter_mut() {
+        if tile.state == TileState::Flagged {
+            continue;
+        }
         let distance_from_mouse_pointer = Vec3::distance(cursor_position, transform.translation);
         let influence = params.mouse_influence(distance_from_mouse_pointer);
         let acceleration = totaloffset * influence / physics_material.density;
@@ -104,6 +284,9 @@ pub fn go_home(
 ) {
     let damping_factor = 0.5;
     for (mut velocity, transform, thingy) in query.iter_mut() {
+        if thingy.state == TileState::Flagged {
+            continue;
+        }
         let distance = thingy.original_position.distance(transform.translation);
         if distance < 0.001 {
             continue;
@@ -1,18 +1,36 @@
 use bevy::prelude::*;
 
 use crate::{
+    map_generator::Map,
     stages::GameStage,
-    tile::{Tile, TileKind},
+    stats::GameStats,
+    tile::{Tile, TileKind, TileState},
 };
 
 #[derive(Debug, Component)]
 pub struct WinScreen;
 
-pub fn draw(asset_server: Res<AssetServer>, mut commands: Commands, query: Query<(&Tile,)>) {
+pub fn draw(
+    asset_server: Res<AssetServer>,
+    stats: Res<GameStats>,
+    mut commands: Commands,
+    query: Query<(&Tile,)>,
+) {
     let font = asset_server.load("fonts/FiraSans-Bold.ttf");
-    let has_yellows = query
+    let hints = match stats.hints {
+        0 => String::new(),
+        1 => "\nwith a little help".to_string(),
+        n => format!("\nwith {n} hints"),
+    };
+    let opening = match stats.biggest_opening {
+        0 | 1 => String::new(),
+        n => format!("\nbiggest opening {n} tiles"),
+    };
+    // Every safe tile is revealed by now, but not every bomb has to be flagged
+    let flagged_every_bomb = query
         .iter()
-        .any(|(tile,)| matches!(tile.kind, TileKind::Danger(_)));
+        .filter(|(tile,)| tile.kind == TileKind::Boom)
+        .all(|(tile,)| tile.state == TileState::Flagged);
 
     commands
         .spawn_bundle(TextBundle {
@@ -40,11 +58,19 @@ pub fn draw(asset_server: Res<AssetServer>, mut commands: Commands, query: Query
                         },
                     },
                     TextSection {
-                        value: if has_yellows {
+                        value: if flagged_every_bomb {
                             "Thanks for being careful".to_string()
                         } else {
                             "Please enjoy life".to_string()
                         },
+                        style: TextStyle {
+                            font: font.clone(),
+                            font_size: 24.0,
+                            color: Color::WHITE,
+                        },
+                    },
+                    TextSection {
+                        value: hints + &opening,
                         style: TextStyle {
                             font,
                             font_size: 24.0,
@@ -66,11 +92,11 @@ pub fn hide(win_screen: Query<(Entity,), With<WinScreen>>, mut commands: Command
     win_screen.for_each(|(e,)| commands.entity(e).despawn());
 }
 
-pub fn you_win(mut state: ResMut<State<GameStage>>, query: Query<(&Tile,)>) {
+pub fn you_win(mut state: ResMut<State<GameStage>>, map: Res<Map>) {
     if *state.current() == GameStage::KillScreen {
         return;
     }
-    if query.iter().all(|(tile,)| tile.kind != TileKind::Fine) {
+    if map.is_cleared() {
         let _ = state.set(GameStage::WinScreen);
     }
 }